@@ -0,0 +1,119 @@
+//! The attribute macros for [errata](https://docs.rs/errata).
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Expr, ItemFn, LitStr, Token};
+
+/// The arguments of `#[catch]`: nothing, or `code = N`.
+struct CatchArgs {
+    code: Option<Expr>,
+}
+
+impl Parse for CatchArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut code = None;
+        while !input.is_empty() {
+            let key: syn::Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "code" => code = Some(input.parse()?),
+                _ => return Err(syn::Error::new(key.span(), "expected `code`")),
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(Self { code })
+    }
+}
+
+/// The arguments of `#[test]`: nothing, or `fails = "msg"`.
+struct TestArgs {
+    fails: Option<LitStr>,
+}
+
+impl Parse for TestArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut fails = None;
+        while !input.is_empty() {
+            let key: syn::Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "fails" => fails = Some(input.parse()?),
+                _ => return Err(syn::Error::new(key.span(), "expected `fails`")),
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(Self { fails })
+    }
+}
+
+/// Wraps the body of `f` in a closure, annotated with its return type where
+/// that's allowed, so `?` and `return` keep working.
+fn closure(f: &ItemFn) -> proc_macro2::TokenStream {
+    let block = &f.block;
+    match &f.sig.output {
+        syn::ReturnType::Type(_, ty) if !matches!(**ty, syn::Type::ImplTrait(_)) => {
+            quote!(move || -> #ty #block)
+        }
+        _ => quote!(move || #block),
+    }
+}
+
+/// Prints failures nicely and exits with their code, instead of panicking.
+///
+/// Use `#[errata::catch(code = N)]` to change the exit code of failures that
+/// don't set their own.
+#[proc_macro_attribute]
+pub fn catch(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as CatchArgs);
+    let mut f = parse_macro_input!(item as ItemFn);
+
+    if let Some(asyncness) = f.sig.asyncness {
+        return syn::Error::new(
+            asyncness.span,
+            "`#[errata::catch]` can't be used on an `async fn`, use `errata::block_on_catch` or `errata::block_on_catch_with` instead",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let set_code = args
+        .code
+        .map(|code| quote!(::errata::set_default_code(#code);));
+    let body = closure(&f);
+    f.block = syn::parse_quote!({
+        #set_code
+        ::errata::__private::catch(#body)
+    });
+
+    quote!(#f).into()
+}
+
+/// Like `#[test]`, but prints errata failures nicely when the test fails.
+///
+/// Use `#[errata::test(fails = "msg")]` for a test that should fail with
+/// that exact message.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as TestArgs);
+    let mut f = parse_macro_input!(item as ItemFn);
+
+    let body = closure(&f);
+    f.block = match args.fails {
+        Some(msg) => {
+            if let syn::ReturnType::Type(_, ty) = &f.sig.output {
+                return syn::Error::new_spanned(ty, "tests with `fails` must return `()`")
+                    .to_compile_error()
+                    .into();
+            }
+            syn::parse_quote!({ ::errata::__private::test_fails(#body, #msg) })
+        }
+        None => syn::parse_quote!({ ::errata::__private::test(#body) }),
+    };
+
+    quote!(#[::core::prelude::v1::test] #f).into()
+}
@@ -0,0 +1,181 @@
+//! Capturing and trimming backtraces for unexpected panics.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::env;
+use std::path::PathBuf;
+
+/// Symbol prefixes of frames that are never interesting to the user: the
+/// standard library, the runtime, the unwinding machinery, and errata itself.
+const HIDDEN_PREFIXES: &[&str] = &[
+    "std::",
+    "core::",
+    "alloc::",
+    "<std::",
+    "<core::",
+    "<alloc::",
+    "errata::",
+    "<errata::",
+    "__rust",
+    "__rustc::",
+    "__libc_start",
+];
+
+/// Symbols of the C runtime and thread start-up, matched exactly so that user
+/// crates with names like `cloner` aren't hidden.
+const HIDDEN_SYMBOLS: &[&str] = &[
+    "main",
+    "<unknown>",
+    "rust_begin_unwind",
+    "_start",
+    "start_thread",
+    "clone",
+    "__clone",
+    "clone3",
+    "__clone3",
+];
+
+/// Captures a backtrace if `force` is set or it's enabled through
+/// `RUST_BACKTRACE` (or `RUST_LIB_BACKTRACE`).
+///
+/// Unless `RUST_BACKTRACE=full`, frames from the standard library, the
+/// runtime and errata are hidden, and paths are shortened to be relative to
+/// the crate root, without a leading `./`.
+pub(crate) fn capture(force: bool) -> Option<String> {
+    let bt = if force {
+        Backtrace::force_capture()
+    } else {
+        Backtrace::capture()
+    };
+    if bt.status() != BacktraceStatus::Captured {
+        return None;
+    }
+
+    if is_full() {
+        Some(format!("{bt:#}"))
+    } else {
+        Some(filter(&bt.to_string()))
+    }
+}
+
+/// Whether the user asked for unfiltered backtraces.
+pub(crate) fn is_full() -> bool {
+    env::var("RUST_BACKTRACE").is_ok_and(|v| v == "full")
+}
+
+/// Filters and renumbers the frames of a rendered backtrace.
+fn filter(bt: &str) -> String {
+    let root = env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::current_dir().ok());
+
+    let mut out = String::new();
+    let mut idx = 0;
+    let mut lines = bt.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some((_, symbol)) = line.trim_start().split_once(": ") else {
+            continue;
+        };
+
+        let mut locations = Vec::new();
+        while let Some(at) = lines.next_if(|l| l.trim_start().starts_with("at ")) {
+            locations.push(at.trim_start().trim_start_matches("at "));
+        }
+
+        if is_hidden(symbol, &locations) {
+            continue;
+        }
+
+        out.push_str(&format!("{idx:>4}: {symbol}\n"));
+        for loc in locations {
+            let loc = root
+                .as_ref()
+                .and_then(|root| loc.strip_prefix(root.to_str()?))
+                .map_or(loc, |rel| rel.trim_start_matches('/'));
+            // std already prints paths relative to the working directory
+            // this way.
+            let loc = loc.strip_prefix("./").unwrap_or(loc);
+            out.push_str(&format!("             at {loc}\n"));
+        }
+
+        idx += 1;
+    }
+
+    out
+}
+
+fn is_hidden(symbol: &str, locations: &[&str]) -> bool {
+    HIDDEN_SYMBOLS.contains(&symbol)
+        || HIDDEN_PREFIXES.iter().any(|p| symbol.starts_with(p))
+        || symbol.contains(" as core::ops::function::Fn")
+        || (!locations.is_empty() && locations.iter().all(|l| l.starts_with("/rustc/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filter, is_hidden};
+
+    #[test]
+    fn hides_runtime_frames_and_renumbers() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let bt = format!(
+            "   0: std::panicking::begin_panic
+             at /rustc/abc/library/std/src/panicking.rs:1:1
+   1: app::parse
+             at {root}/src/parse.rs:10:5
+   2: <F as core::ops::function::FnOnce<()>>::call_once
+             at /rustc/abc/library/core/src/ops/function.rs:2:2
+   3: app::main
+             at {root}/src/main.rs:3:5
+   4: main
+   5: __libc_start_main
+"
+        );
+
+        assert_eq!(
+            filter(&bt),
+            "   0: app::parse
+             at src/parse.rs:10:5
+   1: app::main
+             at src/main.rs:3:5
+"
+        );
+    }
+
+    #[test]
+    fn strips_relative_path_prefixes() {
+        let bt = "   0: app::main\n             at ./src/main.rs:3:5\n";
+        assert_eq!(
+            filter(bt),
+            "   0: app::main\n             at src/main.rs:3:5\n"
+        );
+    }
+
+    #[test]
+    fn keeps_paths_outside_the_crate() {
+        let bt = "   0: dep::run\n             at /home/me/.cargo/registry/dep/src/lib.rs:1:1\n";
+        assert_eq!(filter(bt), bt);
+    }
+
+    #[test]
+    fn hides_frames_only_in_the_standard_library() {
+        assert!(is_hidden(
+            "app::helper",
+            &["/rustc/abc/library/core/src/x.rs:1:1"]
+        ));
+        assert!(!is_hidden("app::helper", &[]));
+        assert!(is_hidden("errata::catch::run", &[]));
+        assert!(is_hidden("<unknown>", &[]));
+    }
+
+    #[test]
+    fn matches_c_runtime_symbols_exactly() {
+        assert!(is_hidden("clone", &[]));
+        assert!(is_hidden("__clone", &[]));
+        assert!(is_hidden("_start", &[]));
+        assert!(is_hidden("__libc_start_call_main", &[]));
+        assert!(!is_hidden("cloner::run", &[]));
+        assert!(!is_hidden("clone_tool::main", &[]));
+        assert!(!is_hidden("_start_server", &[]));
+    }
+}
@@ -0,0 +1,368 @@
+//! The runtime behind `#[errata::catch]`.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::pin::pin;
+use std::process;
+use std::sync::{Arc, Once};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::report::{PanicLocation, PayloadKind, Report};
+use crate::{backtrace, crash, downcast_payload, ErrataPanic};
+
+/// The exit code used for panics that aren't errata failures, same as Rust's.
+const PANIC_CODE: i32 = 101;
+
+thread_local! {
+    /// Whether this thread is running inside [`run`].
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether this thread is running an `#[errata::test]`, which turns
+    /// failures into panics of its own.
+    static TESTING: Cell<bool> = const { Cell::new(false) };
+
+    /// What the panic hook saw of the last unexpected panic on this thread.
+    static LAST_PANIC: RefCell<Option<PanicRecord>> = const { RefCell::new(None) };
+}
+
+/// The details of a panic that are only available to the hook.
+struct PanicRecord {
+    /// The panic's message, to check the record against the payload that's
+    /// eventually caught.
+    message: String,
+    location: Option<PanicLocation>,
+    backtrace: Option<String>,
+}
+
+impl PanicRecord {
+    fn new(info: &PanicHookInfo<'_>) -> Self {
+        Self {
+            message: payload_message(info.payload()).to_string(),
+            location: info.location().map(PanicLocation::from),
+            backtrace: if info.payload().is::<ErrataPanic>() {
+                None
+            } else {
+                backtrace::capture(crash::enabled())
+            },
+        }
+    }
+}
+
+/// Extracts the message from a panic payload, the same way Rust does.
+pub(crate) fn payload_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Installs errata's panic hook, if it isn't already.
+///
+/// The hook records the details of panics on threads running under errata.
+/// Everywhere else, it prints failures itself, since the previous hook would
+/// only show `Box<dyn Any>` for them, and defers to the previous hook for
+/// ordinary panics. Failures in `#[errata::test]`s aren't printed, since the
+/// test panics again with their message.
+pub(crate) fn install_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() {
+                LAST_PANIC.set(Some(PanicRecord::new(info)));
+            } else if let Some(e) = info.payload().downcast_ref::<ErrataPanic>() {
+                if !TESTING.get() {
+                    Report::failure(e).print();
+                }
+            } else {
+                hook(info);
+            }
+        }));
+    });
+}
+
+/// A panic caught by [`run`], along with what the hook saw of it.
+pub(crate) struct Caught {
+    pub(crate) payload: Box<dyn Any + Send>,
+    record: Option<PanicRecord>,
+}
+
+impl Caught {
+    /// Builds the report for the panic.
+    pub(crate) fn report(&self) -> Report {
+        let mut report = report(&*self.payload);
+        if report.kind == PayloadKind::Panic {
+            // `run` catches panics on the thread they happened on.
+            report.thread = thread::current().name().map(ToString::to_string);
+        }
+        if let Some(record) = &self.record {
+            report.location = report.location.or_else(|| record.location.clone());
+            if report.kind == PayloadKind::Panic {
+                report.backtrace = record.backtrace.clone();
+            }
+        }
+        report
+    }
+}
+
+/// Runs `f`, catching any panic along with the details only the hook sees.
+pub(crate) fn run<T>(f: impl FnOnce() -> T) -> Result<T, Caught> {
+    install_hook();
+
+    LAST_PANIC.take();
+    let was_catching = CATCHING.replace(true);
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.set(was_catching);
+
+    // Panics caught inside `f` leave records behind too, so only keep one
+    // that matches the payload. Either way, none is left for later.
+    let record = LAST_PANIC.take();
+    res.map_err(|payload| {
+        let record = record.filter(|r| r.message == payload_message(&*payload));
+        Caught { payload, record }
+    })
+}
+
+/// Builds the report for a payload, without the details only the hook sees.
+pub(crate) fn report(payload: &(dyn Any + Send)) -> Report {
+    match payload.downcast_ref::<ErrataPanic>() {
+        Some(e) => Report::failure(e),
+        None => Report::panic(payload_message(payload), PANIC_CODE),
+    }
+}
+
+/// Runs `f`, printing failures nicely and exiting with the code they carry.
+/// Failures that were already printed by an [`errata::thread`](crate::thread)
+/// are not printed again.
+///
+/// Normal panics are printed with their location and a trimmed backtrace,
+/// then exit with code 101.
+pub fn catch<T>(f: impl FnOnce() -> T) -> T {
+    match run(f) {
+        Ok(t) => t,
+        Err(caught) => {
+            let report = caught.report();
+            if !downcast_payload(&caught.payload).is_some_and(|e| e.reported) {
+                crash::print_fatal(&report);
+            }
+            process::exit(report.code)
+        }
+    }
+}
+
+/// Runs `f`, returning the report of any failure or panic instead of
+/// printing it and exiting. Use this in REPLs and other loops that should
+/// carry on after a failure.
+///
+/// This doesn't replace the panic hook: errata's hook is installed once,
+/// stays quiet only while `f` runs, and defers to the previous hook
+/// otherwise.
+///
+/// Usage:
+/// ```no_run
+/// use errata::FallibleExt;
+///
+/// for line in std::io::stdin().lines() {
+///     let line = line.fail("Could not read input");
+///     match errata::recover(|| line.trim().parse::<i32>().fail("Invalid number")) {
+///         Ok(n) => println!("{}", n * 2),
+///         Err(report) => report.print(),
+///     }
+/// }
+/// ```
+pub fn recover<T>(f: impl FnOnce() -> T) -> Result<T, Box<Report>> {
+    run(f).map_err(|caught| Box::new(caught.report()))
+}
+
+/// Runs the body of an `#[errata::test]`, turning failures into panics whose
+/// message is the failure as errata prints it, so the test harness shows it
+/// nicely.
+///
+/// Ordinary panics are left alone.
+#[track_caller]
+pub fn test<T>(f: impl FnOnce() -> T) -> T {
+    match run_test(f) {
+        Ok(t) => t,
+        Err(payload) => match downcast_payload(&payload) {
+            Some(e) => panic!("{e}"),
+            None => panic::resume_unwind(payload),
+        },
+    }
+}
+
+/// Runs the body of an `#[errata::test(fails = "...")]`, which passes only if
+/// it fails with the `expected` message.
+#[track_caller]
+pub fn test_fails<T>(f: impl FnOnce() -> T, expected: &str) {
+    match run_test(f) {
+        Ok(_) => panic!("expected a failure with message {expected:?}, but there was none"),
+        Err(payload) => match downcast_payload(&payload) {
+            Some(e) if e.message() == expected => {}
+            Some(e) => panic!("expected a failure with message {expected:?}, but got:\n{e}"),
+            None => panic::resume_unwind(payload),
+        },
+    }
+}
+
+/// Runs `f` for [`test`] or [`test_fails`], with its failures kept quiet.
+fn run_test<T>(f: impl FnOnce() -> T) -> thread::Result<T> {
+    install_hook();
+
+    let was_testing = TESTING.replace(true);
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    TESTING.set(was_testing);
+    res
+}
+
+/// Runs a future to completion on the current thread, with the same handling
+/// of failures as `#[errata::catch]`.
+///
+/// This takes the place of `#[errata::catch]` for async programs, with
+/// runtimes that work with any executor, such as async-std or smol. Tokio's
+/// I/O and timers need its own runtime to drive them, so use
+/// [`block_on_catch_with`] there.
+///
+/// Usage:
+/// ```no_run
+/// use errata::FallibleExt;
+///
+/// async fn run() -> Result<(), String> {
+///     Err("nope".to_string())
+/// }
+///
+/// fn main() {
+///     errata::block_on_catch(async {
+///         run().await.fail("Failed to run");
+///     });
+/// }
+/// ```
+pub fn block_on_catch<F: Future>(fut: F) -> F::Output {
+    catch(|| block_on(fut))
+}
+
+/// Like [`block_on_catch`], but runs the future with `runner`, e.g. a tokio
+/// runtime's `block_on`, so it works with any runtime.
+///
+/// Usage:
+/// ```
+/// use std::time::Duration;
+///
+/// async fn run() -> u32 {
+///     tokio::time::sleep(Duration::from_millis(1)).await;
+///     42
+/// }
+///
+/// fn main() {
+///     let rt = tokio::runtime::Runtime::new().unwrap();
+///     let answer = errata::block_on_catch_with(run(), |fut| rt.block_on(fut));
+///     assert_eq!(answer, 42);
+/// }
+/// ```
+///
+/// A runtime can also be started inside `#[errata::catch]`, which only has
+/// to go on a plain `fn`:
+///
+/// ```
+/// # use std::time::Duration;
+/// # async fn run() {
+/// #     tokio::time::sleep(Duration::from_millis(1)).await;
+/// # }
+/// #[errata::catch]
+/// fn main() {
+///     tokio::runtime::Runtime::new().unwrap().block_on(run())
+/// }
+/// ```
+pub fn block_on_catch_with<F: Future>(fut: F, runner: impl FnOnce(F) -> F::Output) -> F::Output {
+    catch(|| runner(fut))
+}
+
+/// Wakes a thread blocked in [`block_on`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(t) => return t,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::{run, LAST_PANIC};
+    use crate::{FallibleExt, PayloadKind};
+
+    #[test]
+    fn records_the_location_of_the_caught_panic() {
+        let line = line!() + 1;
+        let caught = run(|| panic!("outer")).unwrap_err();
+        let report = caught.report();
+
+        assert_eq!(report.kind(), PayloadKind::Panic);
+        assert_eq!(report.message(), "outer");
+        assert_eq!(report.location().map(|l| l.line()), Some(line));
+    }
+
+    #[test]
+    fn ignores_records_of_other_panics() {
+        let caught = run(|| {
+            let _ = panic::catch_unwind(|| panic!("inner"));
+            panic::resume_unwind(Box::new("outer"))
+        })
+        .unwrap_err();
+
+        assert_eq!(caught.report().message(), "outer");
+        assert_eq!(caught.report().location(), None);
+    }
+
+    #[test]
+    fn leaves_no_record_behind() {
+        let res = run(|| {
+            let _ = panic::catch_unwind(|| panic!("inner"));
+        });
+        assert!(res.is_ok());
+        assert!(LAST_PANIC.with_borrow(Option::is_none));
+    }
+
+    #[crate::test]
+    fn runs_tests_that_pass() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[crate::test]
+    #[should_panic(expected = "Missing value")]
+    fn turns_failures_into_test_panics() {
+        None::<i32>.fail("Missing value");
+    }
+
+    #[crate::test(fails = "Missing value")]
+    fn expects_failures() {
+        None::<i32>.fail("Missing value");
+    }
+
+    #[crate::test(fails = "Missing value")]
+    #[should_panic(expected = "but got:\nOther value")]
+    fn rejects_other_failures() {
+        None::<i32>.fail("Other value");
+    }
+}
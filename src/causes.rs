@@ -0,0 +1,162 @@
+//! Walking and printing `std::error::Error` source chains.
+
+use std::error::Error;
+use std::fmt::{self, Write};
+
+/// Errors whose `source` chain [`fail_chain`](crate::ResultExt::fail_chain)
+/// can print: any `std::error::Error`, plus boxed `dyn Error`s, which don't
+/// implement `Error` themselves.
+///
+/// `M` only keeps the implementations apart, and is always inferred.
+pub trait AsDynError<M> {
+    fn as_dyn_error(&self) -> &(dyn Error + '_);
+}
+
+/// The [`AsDynError`] marker for types implementing `Error`.
+pub enum Unboxed {}
+
+/// The [`AsDynError`] marker for boxed `dyn Error`s.
+pub enum Boxed {}
+
+impl<E: Error> AsDynError<Unboxed> for E {
+    fn as_dyn_error(&self) -> &(dyn Error + '_) {
+        self
+    }
+}
+
+impl AsDynError<Boxed> for Box<dyn Error + '_> {
+    fn as_dyn_error(&self) -> &(dyn Error + '_) {
+        &**self
+    }
+}
+
+impl AsDynError<Boxed> for Box<dyn Error + Send + '_> {
+    fn as_dyn_error(&self) -> &(dyn Error + '_) {
+        &**self
+    }
+}
+
+impl AsDynError<Boxed> for Box<dyn Error + Send + Sync + '_> {
+    fn as_dyn_error(&self) -> &(dyn Error + '_) {
+        &**self
+    }
+}
+
+/// Collects the chain of underlying causes of an error.
+///
+/// Each `source` is included unless its text already appears in its
+/// parent's, as is common for errors that embed their source in their own
+/// message.
+pub(crate) fn causes(e: &dyn Error) -> Vec<String> {
+    let mut causes = Vec::new();
+    let mut parent = e.to_string();
+    let mut source = e.source();
+
+    while let Some(err) = source {
+        let text = err.to_string();
+        if !parent.contains(&text) {
+            causes.push(text.clone());
+        }
+
+        parent = text;
+        source = err.source();
+    }
+
+    causes
+}
+
+/// Writes an indented "Caused by:" list, or nothing if there are no causes.
+pub(crate) fn write_causes(f: &mut impl Write, causes: &[String]) -> fmt::Result {
+    match causes {
+        [] => Ok(()),
+        [cause] => write!(f, "\n\nCaused by:\n    {cause}"),
+        causes => {
+            f.write_str("\n\nCaused by:")?;
+            for (i, cause) in causes.iter().enumerate() {
+                write!(f, "\n    {i}: {cause}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fmt::{self, Display};
+    use std::io;
+
+    use super::{causes, write_causes, AsDynError};
+
+    /// An error with a message and an optional source.
+    #[derive(Debug)]
+    struct Wrapped(&'static str, Option<Box<dyn Error + Send + Sync>>);
+
+    impl Display for Wrapped {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl Error for Wrapped {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.1.as_deref().map(|e| e as _)
+        }
+    }
+
+    fn chain() -> Wrapped {
+        let io = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        Wrapped(
+            "invalid config",
+            Some(Box::new(Wrapped(
+                "could not read app.toml",
+                Some(Box::new(io)),
+            ))),
+        )
+    }
+
+    #[test]
+    fn walks_sources() {
+        assert_eq!(
+            causes(&chain()),
+            ["could not read app.toml", "no such file"]
+        );
+    }
+
+    #[test]
+    fn skips_sources_already_in_the_message() {
+        let io = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        let e = Wrapped("could not read app.toml: no such file", Some(Box::new(io)));
+        assert!(causes(&e).is_empty());
+    }
+
+    #[test]
+    fn walks_boxed_errors() {
+        let boxed: Box<dyn Error + Send + Sync> = Box::new(chain());
+        assert_eq!(causes(boxed.as_dyn_error()).len(), 2);
+
+        let boxed: Box<dyn Error> = Box::new(chain());
+        assert_eq!(causes(boxed.as_dyn_error()).len(), 2);
+    }
+
+    #[test]
+    fn writes_one_cause_unnumbered() {
+        let mut out = String::new();
+        write_causes(&mut out, &["no such file".to_string()]).unwrap();
+        assert_eq!(out, "\n\nCaused by:\n    no such file");
+    }
+
+    #[test]
+    fn writes_several_causes_numbered() {
+        let mut out = String::new();
+        write_causes(&mut out, &["a".to_string(), "b".to_string()]).unwrap();
+        assert_eq!(out, "\n\nCaused by:\n    0: a\n    1: b");
+    }
+
+    #[test]
+    fn writes_nothing_without_causes() {
+        let mut out = String::new();
+        write_causes(&mut out, &[]).unwrap();
+        assert_eq!(out, "");
+    }
+}
@@ -0,0 +1,177 @@
+//! Gathering several failures before reporting them all at once.
+
+use std::fmt::Display;
+use std::mem;
+use std::sync::Mutex;
+
+use crate::config::{self, OutputFormat};
+use crate::report::{self, Report};
+use crate::{Diagnostic, ErrataPanic, IntoDiagnostic, Severity};
+
+/// Collects failures that shouldn't stop the program straight away, e.g. one
+/// per invalid input in a batch, and reports them all at the end.
+///
+/// A collector can be shared between threads, e.g. from
+/// [`errata::thread::scope`](crate::thread::scope) or rayon.
+///
+/// Usage:
+/// ```no_run
+/// use errata::Collector;
+///
+/// let errors = Collector::new();
+/// let numbers: Vec<i32> = ["1", "two", "3", "four"]
+///     .into_iter()
+///     .filter_map(|s| errors.collect(s.parse(), format!("Invalid number {s:?}")))
+///     .collect();
+///
+/// // Prints both failures and "2 errors", then exits with code 1.
+/// errors.finish();
+/// ```
+#[derive(Debug, Default)]
+#[must_use = "recorded failures are only reported by `finish`"]
+pub struct Collector {
+    reports: Mutex<Vec<Report>>,
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a failure, to be printed by [`finish`](Collector::finish).
+    #[track_caller]
+    pub fn push(&self, msg: impl IntoDiagnostic) {
+        let report = Report::failure(&ErrataPanic::from(msg.into_diagnostic()));
+        self.reports
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(report);
+    }
+
+    /// Returns the value in `result`, or records the error the way
+    /// [`fail`](crate::FallibleExt::fail) would print it and returns `None`.
+    #[track_caller]
+    pub fn collect<T, E: Display>(
+        &self,
+        result: Result<T, E>,
+        msg: impl IntoDiagnostic,
+    ) -> Option<T> {
+        match result {
+            Ok(t) => Some(t),
+            Err(e) => {
+                self.push(msg.into_diagnostic().with_error(e));
+                None
+            }
+        }
+    }
+
+    /// The number of failures recorded so far.
+    pub fn len(&self) -> usize {
+        self.reports.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Prints every recorded failure in order, followed by a summary such as
+    /// `3 errors`.
+    ///
+    /// If any of them is an error rather than a warning, this then exits
+    /// cleanly with the highest exit code among the errors, like
+    /// [`fail`](crate::FallibleExt::fail). Otherwise it returns.
+    #[track_caller]
+    pub fn finish(mut self) {
+        if let Some((severity, code, summary)) = self.print_reports() {
+            if severity == Severity::Error {
+                let mut e = ErrataPanic::from(Diagnostic::new(summary).exit_code(code));
+                e.reported = true;
+                e.throw()
+            }
+        }
+    }
+
+    /// Prints and forgets the recorded failures and their summary, returning
+    /// the worst severity, the highest exit code among the failures of that
+    /// severity, and the summary.
+    fn print_reports(&mut self) -> Option<(Severity, i32, String)> {
+        let reports = mem::take(self.reports.get_mut().unwrap_or_else(|e| e.into_inner()));
+        let worst = reports.iter().max_by_key(|r| (r.severity, r.code))?;
+
+        for report in &reports {
+            report.print();
+        }
+
+        let count = |severity| reports.iter().filter(|r| r.severity == severity).count();
+        let summary = [
+            (count(Severity::Error), "error"),
+            (count(Severity::Warning), "warning"),
+        ]
+        .into_iter()
+        .filter(|&(n, _)| n > 0)
+        .map(|(n, noun)| format!("{n} {noun}{}", if n == 1 { "" } else { "s" }))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+        // The JSON output is one failure per line, with nothing in between.
+        if config::output() == OutputFormat::Human {
+            let style = worst.severity.style();
+            report::print_line(style.paint(&summary, worst.color));
+        }
+
+        Some((worst.severity, worst.code, summary))
+    }
+}
+
+/// Failures still recorded when a collector is dropped without being
+/// [`finish`](Collector::finish)ed, e.g. when a failure unwinds past it, are
+/// printed rather than lost. Dropping never exits, though.
+impl Drop for Collector {
+    fn drop(&mut self) {
+        self.print_reports();
+    }
+}
+
+/// Runs `f` with a new [`Collector`], then [`finish`](Collector::finish)es it.
+///
+/// Usage:
+/// ```no_run
+/// let inputs = ["1", "two", "3"];
+///
+/// let numbers: Vec<i32> = errata::collect_errors(|errors| {
+///     inputs
+///         .iter()
+///         .filter_map(|s| errors.collect(s.parse(), format!("Invalid number {s:?}")))
+///         .collect()
+/// });
+/// ```
+#[track_caller]
+pub fn collect_errors<T>(f: impl FnOnce(&Collector) -> T) -> T {
+    let collector = Collector::new();
+    let t = f(&collector);
+    collector.finish();
+    t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Collector;
+    use crate::testing::assert_fails;
+    use crate::{Diagnostic, Severity};
+
+    #[test]
+    fn exits_with_the_highest_error_code() {
+        let errors = Collector::new();
+        errors.push("e1");
+        errors.push(Diagnostic::new("e2").exit_code(9));
+        errors.push(
+            Diagnostic::new("w")
+                .exit_code(12)
+                .severity(Severity::Warning),
+        );
+        assert_fails(
+            || errors.finish(),
+            Diagnostic::new("2 errors, 1 warning").exit_code(9),
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Global settings for how failures are reported.
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+use std::sync::RwLock;
+
+use crate::report::{HumanReporter, Reporter};
+
+static DEFAULT_CODE: AtomicI32 = AtomicI32::new(1);
+static COLOR: AtomicU8 = AtomicU8::new(ColorChoice::DEFAULT as u8);
+static OUTPUT: AtomicU8 = AtomicU8::new(UNSET);
+static VERBOSE: AtomicU8 = AtomicU8::new(UNSET);
+static REPORTER: RwLock<Option<Box<dyn Reporter>>> = RwLock::new(None);
+
+/// Marks a setting that hasn't been chosen from code yet.
+const UNSET: u8 = u8::MAX;
+
+/// Whether to print failures in color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ColorChoice {
+    /// Use color if stderr is a terminal, honouring `NO_COLOR`, `CLICOLOR`,
+    /// `CLICOLOR_FORCE` and `TERM=dumb`.
+    Auto,
+    /// Always use color.
+    Always,
+    /// Never use color.
+    Never,
+}
+
+impl ColorChoice {
+    /// `Auto` if feature `color` is enabled, otherwise `Never`.
+    const DEFAULT: ColorChoice = if cfg!(feature = "color") {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+
+    /// Decides whether to use color right now.
+    pub fn resolve(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let var = |name| env::var_os(name).filter(|v| !v.is_empty());
+
+                if var("NO_COLOR").is_some() {
+                    false
+                } else if var("CLICOLOR_FORCE").is_some_and(|v| v != "0") {
+                    true
+                } else if var("CLICOLOR").is_some_and(|v| v == "0")
+                    || var("TERM").is_some_and(|v| v == "dumb")
+                {
+                    false
+                } else {
+                    io::stderr().is_terminal()
+                }
+            }
+        }
+    }
+}
+
+/// How failures are written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OutputFormat {
+    /// Human-readable messages, printed by the [`Reporter`] set with
+    /// [`set_reporter`]. This is the default.
+    Human,
+    /// A single-line JSON object per failure, with the fields `kind`
+    /// (`"failure"` or `"panic"`), `message`, `error_code`, `severity`,
+    /// `fields`, `causes`, `notes`, `snippet`, `code`, `location` and
+    /// `thread`. This ignores [`set_reporter`].
+    Json,
+}
+
+/// Sets the exit code used by failures that don't specify their own.
+///
+/// Defaults to 1. `#[errata::catch(code = N)]` calls this before running
+/// your `main`.
+pub fn set_default_code(code: i32) {
+    DEFAULT_CODE.store(code, Ordering::Relaxed);
+}
+
+pub(crate) fn default_code() -> i32 {
+    DEFAULT_CODE.load(Ordering::Relaxed)
+}
+
+/// Sets whether to print failures in color.
+///
+/// Defaults to [`ColorChoice::Auto`] if feature `color` is enabled, otherwise
+/// [`ColorChoice::Never`].
+pub fn set_color(choice: ColorChoice) {
+    COLOR.store(choice as u8, Ordering::Relaxed);
+}
+
+/// The current color setting.
+pub fn color() -> ColorChoice {
+    match COLOR.load(Ordering::Relaxed) {
+        0 => ColorChoice::Auto,
+        1 => ColorChoice::Always,
+        _ => ColorChoice::Never,
+    }
+}
+
+/// Sets how failures are written to stderr.
+///
+/// If never called, the `ERRATA_OUTPUT` environment variable is used
+/// (`human` or `json`), defaulting to [`OutputFormat::Human`].
+pub fn set_output(format: OutputFormat) {
+    OUTPUT.store(format as u8, Ordering::Relaxed);
+}
+
+/// The current output format.
+pub fn output() -> OutputFormat {
+    match OUTPUT.load(Ordering::Relaxed) {
+        0 => OutputFormat::Human,
+        1 => OutputFormat::Json,
+        _ => match env::var("ERRATA_OUTPUT") {
+            Ok(v) if v.eq_ignore_ascii_case("json") => OutputFormat::Json,
+            _ => OutputFormat::Human,
+        },
+    }
+}
+
+/// Sets whether to print where each failure was raised.
+///
+/// If never called, this is enabled by setting the `ERRATA_VERBOSE`
+/// environment variable to anything other than `0`.
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose as u8, Ordering::Relaxed);
+}
+
+/// Whether verbose mode is enabled.
+pub fn verbose() -> bool {
+    match VERBOSE.load(Ordering::Relaxed) {
+        UNSET => env::var_os("ERRATA_VERBOSE").is_some_and(|v| !v.is_empty() && v != "0"),
+        v => v != 0,
+    }
+}
+
+/// Replaces the reporter used to print failures in human-readable form.
+///
+/// Defaults to [`HumanReporter`].
+pub fn set_reporter(reporter: impl Reporter + 'static) {
+    *REPORTER.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(reporter));
+}
+
+/// Runs `f` with the current human-readable reporter.
+pub(crate) fn with_reporter<T>(f: impl FnOnce(&dyn Reporter) -> T) -> T {
+    let reporter = REPORTER.read().unwrap_or_else(|e| e.into_inner());
+    match reporter.as_deref() {
+        Some(reporter) => f(reporter),
+        None => f(&HumanReporter),
+    }
+}
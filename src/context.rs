@@ -0,0 +1,138 @@
+//! A `?`-based alternative to [`fail`](crate::FallibleExt::fail) that
+//! doesn't unwind.
+
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::ops::{ControlFlow, FromResidual, Try};
+use std::process::{ExitCode, Termination};
+
+use crate::report::Report;
+use crate::{Diagnostic, ErrataPanic, IntoDiagnostic};
+
+/// A return type for `main` that prints errors the same way as
+/// [`fail`](crate::FallibleExt::fail), but without unwinding.
+///
+/// Any `Result` whose error converts into `E` can be propagated with `?`.
+/// A `String` is enough for plain messages:
+///
+/// ```no_run
+/// use errata::{FatalError, WithContext};
+///
+/// fn main() -> FatalError<(), String> {
+///     let number: i32 = "abc".parse().context("Invalid user input")?;
+///     println!("{number}");
+///     ().into()
+/// }
+/// ```
+///
+/// Use `E = Diagnostic` to keep any notes and error codes structured:
+///
+/// ```no_run
+/// use errata::{Diagnostic, FatalError, WithContext};
+///
+/// fn main() -> FatalError<(), Diagnostic> {
+///     let number: i32 = "abc".parse().context("Invalid user input")?;
+///     println!("{number}");
+///     ().into()
+/// }
+/// ```
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatalError<T, E>(pub Result<T, E>);
+
+impl<T, E> From<T> for FatalError<T, E> {
+    fn from(t: T) -> Self {
+        Self(Ok(t))
+    }
+}
+
+impl<T, E: IntoDiagnostic> From<Result<T, E>> for FatalError<T, E> {
+    fn from(res: Result<T, E>) -> Self {
+        Self(res)
+    }
+}
+
+impl<T, E> Try for FatalError<T, E> {
+    type Output = T;
+    type Residual = Result<Infallible, E>;
+
+    fn from_output(t: T) -> Self {
+        Self(Ok(t))
+    }
+
+    fn branch(self) -> ControlFlow<Self::Residual, T> {
+        match self.0 {
+            Ok(t) => ControlFlow::Continue(t),
+            Err(e) => ControlFlow::Break(Err(e)),
+        }
+    }
+}
+
+impl<T, E, F: Into<E>> FromResidual<Result<Infallible, F>> for FatalError<T, E> {
+    fn from_residual(residual: Result<Infallible, F>) -> Self {
+        match residual {
+            Err(e) => Self(Err(e.into())),
+        }
+    }
+}
+
+impl<T: Termination, E: IntoDiagnostic> Termination for FatalError<T, E> {
+    fn report(self) -> ExitCode {
+        match self.0 {
+            Ok(t) => t.report(),
+            Err(e) => {
+                let e = ErrataPanic::from(e.into_diagnostic()).without_location();
+                let report = Report::failure(&e);
+                report.print();
+                ExitCode::from(exit_status(report.code))
+            }
+        }
+    }
+}
+
+/// The exit status for a failure's exit code. `main` can only return a `u8`,
+/// so codes that don't fit become 1 rather than wrapping around, possibly to
+/// 0, i.e. success.
+fn exit_status(code: i32) -> u8 {
+    u8::try_from(code).ok().filter(|&c| c != 0).unwrap_or(1)
+}
+
+/// The trait providing [`context`](WithContext::context).
+/// Implemented for `Option<T>` and `Result<T, E: Display>`.
+pub trait WithContext<T> {
+    /// Turns the error into a [`Diagnostic`], the same way
+    /// [`fail`](crate::FallibleExt::fail) does, for use with `?` in a
+    /// function returning [`FatalError`].
+    fn context(self, msg: impl IntoDiagnostic) -> Result<T, Diagnostic>;
+}
+
+impl<T> WithContext<T> for Option<T> {
+    fn context(self, msg: impl IntoDiagnostic) -> Result<T, Diagnostic> {
+        self.ok_or_else(|| msg.into_diagnostic())
+    }
+}
+
+impl<T, E: Display> WithContext<T> for Result<T, E> {
+    fn context(self, msg: impl IntoDiagnostic) -> Result<T, Diagnostic> {
+        self.map_err(|e| msg.into_diagnostic().with_error(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::exit_status;
+
+    #[test]
+    fn keeps_exit_codes_that_fit() {
+        assert_eq!(exit_status(1), 1);
+        assert_eq!(exit_status(2), 2);
+        assert_eq!(exit_status(255), 255);
+    }
+
+    #[test]
+    fn never_exits_successfully() {
+        assert_eq!(exit_status(0), 1);
+        assert_eq!(exit_status(256), 1);
+        assert_eq!(exit_status(-1), 1);
+    }
+}
@@ -0,0 +1,135 @@
+//! Crash report files for unexpected panics.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, OutputFormat};
+use crate::report::{self, PayloadKind, Report};
+
+/// The package name and version of the program, if crash reports are enabled.
+static PACKAGE: RwLock<Option<(&'static str, &'static str)>> = RwLock::new(None);
+
+/// Enables crash report files for unexpected panics, i.e. panics that aren't
+/// errata failures. Usually called through [`crash_reports`](crate::crash_reports).
+///
+/// Instead of the usual panic message, `#[errata::catch]` then writes the
+/// details to a file and prints a short apology with its path, so users can
+/// attach it to a bug report. The file goes in `$XDG_STATE_HOME/<name>` if
+/// that's set, otherwise in the temporary directory.
+///
+/// Failures from [`fail`](crate::FallibleExt::fail) and friends are printed
+/// as usual.
+pub fn set_crash_reports(name: &'static str, version: &'static str) {
+    *PACKAGE.write().unwrap_or_else(|e| e.into_inner()) = Some((name, version));
+}
+
+/// Whether crash reports are enabled, in which case backtraces are always
+/// captured.
+pub(crate) fn enabled() -> bool {
+    PACKAGE.read().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// Prints a failure or panic that's about to end the process, writing a
+/// crash report instead for unexpected panics if enabled.
+pub(crate) fn print_fatal(report: &Report) {
+    let package = *PACKAGE.read().unwrap_or_else(|e| e.into_inner());
+    if let Some((name, version)) = package {
+        if report.kind == PayloadKind::Panic && config::output() == OutputFormat::Human {
+            if let Ok(path) = write(name, version, report) {
+                report::print_line(format_args!(
+                    "{name} crashed unexpectedly, sorry about that!\n\
+                     A report with the details was written to {}\n\
+                     Please include it if you report this problem.",
+                    path.display()
+                ));
+                return;
+            }
+        }
+    }
+
+    report.print();
+}
+
+/// Writes a crash report file, returning its path.
+fn write(name: &str, version: &str, report: &Report) -> io::Result<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    let text = report::format_with(|text| {
+        writeln!(text, "name: {name}")?;
+        writeln!(text, "version: {version}")?;
+        writeln!(text, "time: {}", timestamp(now))?;
+        writeln!(text, "os: {} ({})", env::consts::OS, env::consts::ARCH)?;
+        writeln!(text, "argv: {:?}", env::args_os().collect::<Vec<_>>())?;
+        writeln!(text, "thread: {}", report.thread().unwrap_or("<unnamed>"))?;
+        writeln!(text, "message: {}", report.message())?;
+        match report.location() {
+            Some(loc) => writeln!(text, "location: {loc}")?,
+            None => writeln!(text, "location: unknown")?,
+        }
+        match report.backtrace() {
+            Some(bt) => write!(text, "\nstack backtrace:\n{bt}"),
+            None => writeln!(text, "\nstack backtrace: not captured"),
+        }
+    });
+
+    let dir = match env::var_os("XDG_STATE_HOME").filter(|v| !v.is_empty()) {
+        Some(state) => PathBuf::from(state).join(name),
+        None => env::temp_dir(),
+    };
+    fs::create_dir_all(&dir)?;
+
+    let path = dir.join(format!("{name}-crash-{now}-{}.txt", process::id()));
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+fn timestamp(secs: u64) -> String {
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+    // Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::timestamp;
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(1_792_154_889), "2026-10-16T12:48:09Z");
+    }
+
+    #[test]
+    fn handles_leap_days() {
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(951_868_799), "2000-02-29T23:59:59Z");
+        assert_eq!(timestamp(1_709_164_800), "2024-02-29T00:00:00Z");
+        // 2100 is not a leap year.
+        assert_eq!(timestamp(4_107_542_400), "2100-03-01T00:00:00Z");
+    }
+}
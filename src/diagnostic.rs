@@ -0,0 +1,218 @@
+//! The builder behind every errata failure.
+
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use crate::snippet::{self, Source, SpanLabel};
+use crate::{causes, report, ErrataPanic, Severity, Style};
+
+/// What kind of extra information a [`Note`] gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Label {
+    /// Background information, e.g. what was tried.
+    Note,
+    /// A suggestion for how to fix the problem.
+    Help,
+}
+
+impl Label {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Label::Note => "note",
+            Label::Help => "help",
+        }
+    }
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A labelled line printed under a failure's message, like rustc's
+/// `= help: ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub label: Label,
+    pub text: String,
+}
+
+/// A failure under construction.
+///
+/// [`fail`](crate::FallibleExt::fail), [`error`](crate::error) and
+/// [`context`](crate::WithContext::context) all build one of these, so
+/// everything they print can also be built by hand.
+///
+/// Usage:
+/// ```no_run
+/// use errata::Diagnostic;
+///
+/// let path = "/etc/app.toml";
+///
+/// Diagnostic::new("Could not read config")
+///     .code("E042")
+///     .field("path", path)
+///     .help("create the file, or pass --config")
+///     .exit_code(3)
+///     .fail();
+/// ```
+///
+/// It can also be passed to anything that takes a message, e.g.
+/// `result.fail(Diagnostic::new("Could not connect").help("is it running?"))`.
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic(pub(crate) Box<Inner>);
+
+/// The contents of a [`Diagnostic`], boxed to keep `Result<T, Diagnostic>`
+/// small.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Inner {
+    pub(crate) message: String,
+    pub(crate) code: Option<String>,
+    pub(crate) causes: Vec<String>,
+    pub(crate) notes: Vec<Note>,
+    pub(crate) fields: Vec<(String, String)>,
+    pub(crate) source: Option<Source>,
+    pub(crate) labels: Vec<SpanLabel>,
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) severity: Severity,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Display) -> Self {
+        Self(Box::new(Inner {
+            message: message.to_string(),
+            code: None,
+            causes: Vec::new(),
+            notes: Vec::new(),
+            fields: Vec::new(),
+            source: None,
+            labels: Vec::new(),
+            exit_code: None,
+            severity: Severity::default(),
+        }))
+    }
+
+    /// Sets a stable error code, such as `E042`, printed before the message.
+    ///
+    /// If the code has an [explanation](crate::explanations), a hint to
+    /// `--explain` it is printed too.
+    pub fn code(mut self, code: impl Display) -> Self {
+        self.0.code = Some(code.to_string());
+        self
+    }
+
+    /// Adds a `note:` line, for background information.
+    pub fn note(mut self, text: impl Display) -> Self {
+        self.0.notes.push(Note {
+            label: Label::Note,
+            text: text.to_string(),
+        });
+        self
+    }
+
+    /// Adds a `help:` line, for suggestions on how to fix the problem.
+    pub fn help(mut self, text: impl Display) -> Self {
+        self.0.notes.push(Note {
+            label: Label::Help,
+            text: text.to_string(),
+        });
+        self
+    }
+
+    /// Adds a named value relevant to the failure, such as a path.
+    pub fn field(mut self, key: impl Display, value: impl Display) -> Self {
+        self.0.fields.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Attaches the source text that [`label`](Diagnostic::label) spans point
+    /// into, printed as a snippet under the message.
+    pub fn source(mut self, source: Source) -> Self {
+        self.0.source = Some(source);
+        self
+    }
+
+    /// Marks a byte range of the [`source`](Diagnostic::source) with carets
+    /// and `text`. Labels are ignored if there's no source.
+    pub fn label(mut self, span: Range<usize>, text: impl Display) -> Self {
+        self.0.labels.push(SpanLabel {
+            span,
+            text: text.to_string(),
+        });
+        self
+    }
+
+    /// Sets the exit code, overriding [`set_default_code`](crate::set_default_code).
+    pub fn exit_code(mut self, code: i32) -> Self {
+        self.0.exit_code = Some(code);
+        self
+    }
+
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.0.severity = severity;
+        self
+    }
+
+    /// Appends an error to the message, the way `fail` does for `Result`s.
+    pub(crate) fn with_error(mut self, e: impl Display) -> Self {
+        self.0.message = format!("{}: {e}", self.0.message);
+        self
+    }
+
+    /// Exits the program cleanly, calling destructors and printing the
+    /// diagnostic.
+    #[track_caller]
+    pub fn fail(self) -> ! {
+        ErrataPanic::from(self).throw()
+    }
+}
+
+impl Diagnostic {
+    /// Writes the diagnostic as plain text, the way it reads without color.
+    pub(crate) fn write_plain(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        if let Some(code) = &self.0.code {
+            write!(f, "{}[{code}]: ", self.0.severity)?;
+        }
+        write!(f, "{}", self.0.message)?;
+
+        if let Some(source) = &self.0.source {
+            snippet::write_snippet(f, source, &self.0.labels, Style::PLAIN, false)?;
+        }
+
+        for (key, value) in &self.0.fields {
+            write!(f, "\n    {key}: {value}")?;
+        }
+        causes::write_causes(f, &self.0.causes)?;
+        for note in &self.0.notes {
+            write!(f, "\n  = {}: {}", note.label, note.text)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Anything that can be used as the message of a failure: any `Display`
+/// type, or a [`Diagnostic`] for more control.
+pub trait IntoDiagnostic {
+    fn into_diagnostic(self) -> Diagnostic;
+}
+
+impl<D: Display> IntoDiagnostic for D {
+    fn into_diagnostic(self) -> Diagnostic {
+        Diagnostic::new(self)
+    }
+}
+
+impl IntoDiagnostic for Diagnostic {
+    fn into_diagnostic(self) -> Diagnostic {
+        self
+    }
+}
+
+impl From<Diagnostic> for String {
+    fn from(diag: Diagnostic) -> Self {
+        report::format_with(|f| diag.write_plain(f))
+    }
+}
@@ -0,0 +1,52 @@
+//! Long-form explanations of error codes, for `--explain`.
+
+use std::env;
+use std::path::Path;
+use std::sync::RwLock;
+
+static EXPLANATIONS: RwLock<&'static [(&'static str, &'static str)]> = RwLock::new(&[]);
+
+/// Registers long-form explanations for error codes set with
+/// [`Diagnostic::code`](crate::Diagnostic::code), replacing any registered
+/// before. Usually called through [`explanations`](crate::explanations).
+///
+/// Failures with a registered code print a hint to run your program with
+/// `--explain CODE`, so that's the flag you should handle with [`explain`].
+pub fn set_explanations(table: &'static [(&'static str, &'static str)]) {
+    *EXPLANATIONS.write().unwrap_or_else(|e| e.into_inner()) = table;
+}
+
+/// The long-form explanation registered for an error code, if any.
+///
+/// Usage:
+/// ```no_run
+/// errata::explanations! {
+///     E0042 => "The config file could not be read.\n\nCheck that it exists.",
+/// }
+///
+/// let mut args = std::env::args().skip(1);
+/// if args.next().as_deref() == Some("--explain") {
+///     let code = args.next().unwrap_or_default();
+///     match errata::explain(&code) {
+///         Some(text) => println!("{text}"),
+///         None => errata::error!("no explanation for error code `{code}`"),
+///     }
+/// }
+/// ```
+pub fn explain(code: &str) -> Option<&'static str> {
+    let table = *EXPLANATIONS.read().unwrap_or_else(|e| e.into_inner());
+    table
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, text)| *text)
+}
+
+/// The hint printed under failures whose code has an explanation, like
+/// rustc's.
+pub(crate) fn hint(code: &str) -> String {
+    let program = env::args_os()
+        .next()
+        .and_then(|arg0| Some(Path::new(&arg0).file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "this program".to_string());
+    format!("For more information about this error, try `{program} --explain {code}`.")
+}
@@ -16,44 +16,166 @@
 //! continue to use `unwrap` and `expect` where you don't expect any errors,
 //! and you will continue to get useful debug information just like normal.
 //!
-//! If you wish to throw your own errors, see [`error`].
+//! To print the `source` chain of a `std::error::Error` too, use
+//! [`fail_chain`](ResultExt::fail_chain). For errors that only implement
+//! `Debug`, use [`fail_debug`](ResultExt::fail_debug) instead.
+//!
+//! If you wish to throw your own errors, see [`error`], or build a
+//! [`Diagnostic`] for full control over what's printed.
+//!
+//! To get the same treatment for failures in other threads, spawn them with
+//! [`thread::spawn`] or [`thread::scope`].
+//!
+//! To carry on after a failure instead, e.g. in a REPL, run the code with
+//! [`recover`].
+//!
+//! To report several failures before exiting, e.g. one per invalid input,
+//! gather them in a [`Collector`].
+//!
+//! If you'd rather not unwind at all, return a [`FatalError`] from `main` and
+//! use [`context`](WithContext::context) with `?` instead.
+//!
+//! Set `ERRATA_VERBOSE=1` (or call [`set_verbose`]) to also print where each
+//! failure was raised.
+//!
+//! Failures exit with code 1 by default. This can be changed globally with
+//! [`set_default_code`] (or `#[errata::catch(code = N)]`), or per failure with
+//! [`fail_code`](FallibleExt::fail_code) and `error!(code = N, ...)`.
 
-use std::fmt::Display;
+#![feature(try_trait_v2)]
 
-pub use errata_macros::catch;
+use std::any::Any;
+use std::fmt::{Debug, Display};
+use std::ops::Range;
 
-#[doc(hidden)]
-#[derive(Debug)]
-pub struct ErrataPanic(pub String);
+pub use errata_macros::{catch, test};
 
-impl Display for ErrataPanic {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+// Lets the crate's own tests use its attributes, which expand to `::errata`
+// paths.
+#[cfg(test)]
+extern crate self as errata;
+
+use catch::payload_message;
+
+mod backtrace;
+mod catch;
+mod causes;
+mod collector;
+mod config;
+mod context;
+mod crash;
+mod diagnostic;
+mod explain;
+mod payload;
+mod report;
+mod snippet;
+mod style;
+pub mod testing;
+pub mod thread;
+
+pub use catch::{block_on_catch, block_on_catch_with, recover};
+pub use causes::AsDynError;
+pub use collector::{collect_errors, Collector};
+pub use config::{
+    color, output, set_color, set_default_code, set_output, set_reporter, set_verbose, verbose,
+    ColorChoice, OutputFormat,
+};
+pub use context::{FatalError, WithContext};
+pub use crash::set_crash_reports;
+pub use diagnostic::{Diagnostic, IntoDiagnostic, Label, Note};
+pub use explain::{explain, set_explanations};
+pub use payload::{downcast_payload, ErrataPanic, Severity};
+pub use report::{
+    render, render_to, HumanReporter, JsonReporter, PanicLocation, PayloadKind, RenderOptions,
+    Report, Reporter,
+};
+pub use snippet::{Source, SpanLabel};
+pub use style::{Painted, Style};
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::catch::{catch, test, test_fails};
 }
 
 /// Exits the program cleanly, calling destructors and printing an error message.
-/// Uses the same syntax as `format`.
+/// Uses the same syntax as `format`, optionally preceded by any of these:
 ///
-/// If you enable feature `color`, prints in bold red.
-#[cfg(not(feature = "color"))]
+/// - `code = N,` to set the exit code.
+/// - `error_code = "E0042",` to set a stable error code (see
+///   [`Diagnostic::code`]).
+/// - `note = "...",` to add a `note:` line under the message.
+/// - `help = "...",` to add a `help:` line under the message.
+///
+/// If color is enabled (see [`set_color`]), prints in bold red.
+///
+/// Usage:
+/// ```no_run
+/// let var = "DATABASE_URL";
+///
+/// errata::error!(code = 2, help = "set DATABASE_URL or pass --db", "{var} is not set");
+/// ```
 #[macro_export]
 macro_rules! error {
     ($($arg:tt)*) => {
-        std::panic::panic_any($crate::ErrataPanic(format!($($arg)*)));
-    }
+        $crate::__error!([] $($arg)*)
+    };
 }
 
-/// Exits the program cleanly, calling destructors and printing an error message.
-/// Uses the same syntax as `format`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __error {
+    ([$($opt:tt)*] code = $code:expr, $($rest:tt)*) => {
+        $crate::__error!([$($opt)* .exit_code($code)] $($rest)*)
+    };
+    ([$($opt:tt)*] error_code = $error_code:expr, $($rest:tt)*) => {
+        $crate::__error!([$($opt)* .code($error_code)] $($rest)*)
+    };
+    ([$($opt:tt)*] note = $note:expr, $($rest:tt)*) => {
+        $crate::__error!([$($opt)* .note($note)] $($rest)*)
+    };
+    ([$($opt:tt)*] help = $help:expr, $($rest:tt)*) => {
+        $crate::__error!([$($opt)* .help($help)] $($rest)*)
+    };
+    ([$($opt:tt)*] $($arg:tt)*) => {
+        $crate::Diagnostic::new(format!($($arg)*)) $($opt)* .fail()
+    };
+}
+
+/// Registers long-form explanations for error codes, shown by [`explain`].
+/// Each explanation must be a constant expression, such as a string literal
+/// or `include_str!("E0042.md")`.
 ///
-/// If you enable feature `color`, prints in bold red.
-#[cfg(feature = "color")]
+/// Usage:
+/// ```no_run
+/// errata::explanations! {
+///     E0041 => "The config file is not valid TOML.",
+///     E0042 => "The config file could not be read.",
+/// }
+/// ```
 #[macro_export]
-macro_rules! error {
-    ($($arg:tt)*) => {
-        std::panic::panic_any($crate::ErrataPanic(format!("\x1b[38;5;1m\x1b[1m{}\x1b[0m", format!($($arg)*))));
-    }
+macro_rules! explanations {
+    ($($code:ident => $text:expr),* $(,)?) => {
+        $crate::set_explanations(&[$((stringify!($code), $text)),*])
+    };
+}
+
+/// Enables crash report files for unexpected panics, with the name and
+/// version of the calling package. See [`set_crash_reports`].
+///
+/// Usage:
+/// ```no_run
+/// #[errata::catch]
+/// fn main() {
+///     errata::crash_reports!();
+///
+///     // ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! crash_reports {
+    () => {
+        $crate::set_crash_reports(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    };
 }
 
 /// The trait providing [`fail`](FallibleExt::fail).
@@ -61,7 +183,13 @@ macro_rules! error {
 pub trait FallibleExt<T> {
     /// Exits the program cleanly, calling destructors and printing an error message.
     ///
-    /// If you enable feature `color`, prints in bold red.
+    /// For a `Result`, only the error's own message is printed, not its
+    /// `source` chain. `fail` takes any `Display` error, and telling which of
+    /// those are `std::error::Error`s would take specialization, which isn't
+    /// sound yet. Use [`fail_chain`](ResultExt::fail_chain) for errors whose
+    /// root cause matters.
+    ///
+    /// If color is enabled (see [`set_color`]), prints in bold red.
     ///
     /// Usage:
     /// ```no_run
@@ -71,44 +199,292 @@ pub trait FallibleExt<T> {
     /// // Prints the text verbatim to stderr, then exits with code 1.
     /// bad.fail("Expected bad to contain a value");
     /// ```
-    fn fail(self, msg: impl Display) -> T;
+    fn fail(self, msg: impl IntoDiagnostic) -> T;
+
+    /// Like [`fail`](FallibleExt::fail), but exits with the given code.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// # use errata::FallibleExt;
+    /// let bad: Result<i32, _> = "abc".parse::<i32>();
+    ///
+    /// // Prints the error to stderr, then exits with code 2.
+    /// bad.fail_code(2, "Invalid number");
+    /// ```
+    fn fail_code(self, code: i32, msg: impl IntoDiagnostic) -> T;
+
+    /// Like [`fail`](FallibleExt::fail), but only builds the message if
+    /// there's an error.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// # use errata::FallibleExt;
+    /// let path = "config.toml";
+    /// let bad: Option<i32> = None;
+    ///
+    /// bad.fail_with(|| format!("Missing value in {path}"));
+    /// ```
+    fn fail_with<M: IntoDiagnostic>(self, msg: impl FnOnce() -> M) -> T;
+
+    /// Like [`fail`](FallibleExt::fail), but also prints the line of `source`
+    /// containing `span`, with carets under the span. For `Result`s, the
+    /// error is printed next to the carets instead of after the message.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use errata::{FallibleExt, Source};
+    ///
+    /// let source = Source::new("app.toml", "port = \"eighty\"\n");
+    /// let bad: Result<u16, _> = "eighty".parse::<u16>();
+    ///
+    /// // Prints the following to stderr, then exits with code 1:
+    /// // Invalid config
+    /// //  --> app.toml:1:8
+    /// //   |
+    /// // 1 | port = "eighty"
+    /// //   |        ^^^^^^^^ invalid digit found in string
+    /// bad.fail_at(&source, 7..15, "Invalid config");
+    /// ```
+    fn fail_at(self, source: &Source, span: Range<usize>, msg: impl IntoDiagnostic) -> T;
 }
 
 impl<T> FallibleExt<T> for Option<T> {
-    #[cfg(not(feature = "color"))]
-    fn fail(self, msg: impl Display) -> T {
+    #[track_caller]
+    fn fail(self, msg: impl IntoDiagnostic) -> T {
         match self {
             Some(t) => t,
-            None => std::panic::panic_any(ErrataPanic(format!("{msg}"))),
+            None => msg.into_diagnostic().fail(),
         }
     }
 
-    #[cfg(feature = "color")]
-    fn fail(self, msg: impl Display) -> T {
+    #[track_caller]
+    fn fail_code(self, code: i32, msg: impl IntoDiagnostic) -> T {
         match self {
             Some(t) => t,
-            None => std::panic::panic_any(ErrataPanic(format!("\x1b[38;5;1m\x1b[1m{msg}\x1b[0m"))),
+            None => msg.into_diagnostic().exit_code(code).fail(),
+        }
+    }
+
+    #[track_caller]
+    fn fail_with<M: IntoDiagnostic>(self, msg: impl FnOnce() -> M) -> T {
+        match self {
+            Some(t) => t,
+            None => msg().into_diagnostic().fail(),
+        }
+    }
+
+    #[track_caller]
+    fn fail_at(self, source: &Source, span: Range<usize>, msg: impl IntoDiagnostic) -> T {
+        match self {
+            Some(t) => t,
+            None => msg
+                .into_diagnostic()
+                .source(source.clone())
+                .label(span, "")
+                .fail(),
         }
     }
 }
 
-// TODO: should there also be impl for E: !Display?
 impl<T, E: Display> FallibleExt<T> for Result<T, E> {
-    #[cfg(not(feature = "color"))]
-    fn fail(self, msg: impl Display) -> T {
+    #[track_caller]
+    fn fail(self, msg: impl IntoDiagnostic) -> T {
+        match self {
+            Ok(t) => t,
+            Err(e) => msg.into_diagnostic().with_error(e).fail(),
+        }
+    }
+
+    #[track_caller]
+    fn fail_code(self, code: i32, msg: impl IntoDiagnostic) -> T {
+        match self {
+            Ok(t) => t,
+            Err(e) => msg.into_diagnostic().with_error(e).exit_code(code).fail(),
+        }
+    }
+
+    #[track_caller]
+    fn fail_with<M: IntoDiagnostic>(self, msg: impl FnOnce() -> M) -> T {
+        match self {
+            Ok(t) => t,
+            Err(e) => msg().into_diagnostic().with_error(e).fail(),
+        }
+    }
+
+    #[track_caller]
+    fn fail_at(self, source: &Source, span: Range<usize>, msg: impl IntoDiagnostic) -> T {
+        match self {
+            Ok(t) => t,
+            Err(e) => msg
+                .into_diagnostic()
+                .source(source.clone())
+                .label(span, e)
+                .fail(),
+        }
+    }
+}
+
+/// The trait providing extra failure methods for `Result<T, E>`, whatever
+/// `E` is.
+pub trait ResultExt<T, E> {
+    /// Like [`fail`](FallibleExt::fail), but for errors that only implement
+    /// `Debug`. The error is pretty-printed underneath the message.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use errata::ResultExt;
+    ///
+    /// #[derive(Debug)]
+    /// struct ParseError {
+    ///     line: usize,
+    /// }
+    ///
+    /// let bad: Result<i32, _> = Err(ParseError { line: 3 });
+    ///
+    /// // Prints the following to stderr, then exits with code 1:
+    /// // Invalid config:
+    /// //     ParseError {
+    /// //         line: 3,
+    /// //     }
+    /// bad.fail_debug("Invalid config");
+    /// ```
+    fn fail_debug(self, msg: impl IntoDiagnostic) -> T
+    where
+        E: Debug;
+
+    /// Exits the program cleanly, calling destructors and printing the
+    /// message built from the error by `msg`. Unlike
+    /// [`fail_with`](FallibleExt::fail_with), the error itself isn't printed.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use errata::ResultExt;
+    ///
+    /// struct ParseError {
+    ///     line: usize,
+    /// }
+    ///
+    /// let bad: Result<i32, _> = Err(ParseError { line: 3 });
+    ///
+    /// // Prints "Invalid config on line 3" to stderr, then exits with code 1.
+    /// bad.fail_with_err(|e| format!("Invalid config on line {}", e.line));
+    /// ```
+    fn fail_with_err<M: IntoDiagnostic>(self, msg: impl FnOnce(E) -> M) -> T;
+
+    /// Like [`fail`](FallibleExt::fail), but also prints the error's chain of
+    /// `source`s underneath the message, leaving out any whose text already
+    /// appears in the error above it.
+    ///
+    /// Works for any `std::error::Error`, as well as `Box<dyn Error>` (with
+    /// or without `Send + Sync`).
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use errata::ResultExt;
+    ///
+    /// let res: Result<String, Box<dyn std::error::Error>> =
+    ///     std::fs::read_to_string("app.toml").map_err(Into::into);
+    ///
+    /// res.fail_chain("Could not load config");
+    /// ```
+    fn fail_chain<M>(self, msg: impl IntoDiagnostic) -> T
+    where
+        E: AsDynError<M>;
+}
+
+impl<T, E> ResultExt<T, E> for Result<T, E> {
+    #[track_caller]
+    fn fail_debug(self, msg: impl IntoDiagnostic) -> T
+    where
+        E: Debug,
+    {
+        match self {
+            Ok(t) => t,
+            Err(e) => {
+                let mut diag = msg.into_diagnostic();
+                let debug = format!("{e:#?}").replace('\n', "\n    ");
+                diag.0.message = format!("{}:\n    {debug}", diag.0.message);
+                diag.fail()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn fail_with_err<M: IntoDiagnostic>(self, msg: impl FnOnce(E) -> M) -> T {
         match self {
             Ok(t) => t,
-            Err(e) => std::panic::panic_any(ErrataPanic(format!("{msg}: {e}"))),
+            Err(e) => msg(e).into_diagnostic().fail(),
         }
     }
 
-    #[cfg(feature = "color")]
-    fn fail(self, msg: impl Display) -> T {
+    #[track_caller]
+    fn fail_chain<M>(self, msg: impl IntoDiagnostic) -> T
+    where
+        E: AsDynError<M>,
+    {
         match self {
             Ok(t) => t,
             Err(e) => {
-                std::panic::panic_any(ErrataPanic(format!("\x1b[38;5;1m\x1b[1m{msg}: {e}\x1b[0m")))
+                let e = e.as_dyn_error();
+                let mut diag = msg.into_diagnostic().with_error(e);
+                diag.0.causes = causes::causes(e);
+                diag.fail()
             }
         }
     }
 }
+
+/// The trait providing [`fail`](PayloadExt::fail) for results holding a panic
+/// payload, such as those from [`JoinHandle::join`](std::thread::JoinHandle::join)
+/// and [`catch_unwind`](std::panic::catch_unwind).
+///
+/// This is separate from [`FallibleExt`] because `Box<dyn Any + Send>`
+/// doesn't implement `Display`.
+pub trait PayloadExt<T> {
+    /// Exits the program cleanly, calling destructors and printing an error message.
+    ///
+    /// If the payload is an [`ErrataPanic`], it's raised again unchanged.
+    /// Otherwise, its message is extracted the same way as for any panic.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use errata::PayloadExt;
+    ///
+    /// let handle = std::thread::spawn(|| panic!("oh no"));
+    ///
+    /// // Prints "Worker failed: oh no" to stderr, then exits with code 1.
+    /// handle.join().fail("Worker failed");
+    /// ```
+    fn fail(self, msg: impl IntoDiagnostic) -> T;
+
+    /// Like [`fail`](PayloadExt::fail), but exits with the given code unless
+    /// the payload is an [`ErrataPanic`].
+    fn fail_code(self, code: i32, msg: impl IntoDiagnostic) -> T;
+}
+
+impl<T> PayloadExt<T> for Result<T, Box<dyn Any + Send>> {
+    #[track_caller]
+    fn fail(self, msg: impl IntoDiagnostic) -> T {
+        match self {
+            Ok(t) => t,
+            Err(p) if p.is::<ErrataPanic>() => std::panic::resume_unwind(p),
+            Err(p) => msg
+                .into_diagnostic()
+                .with_error(payload_message(&*p))
+                .fail(),
+        }
+    }
+
+    #[track_caller]
+    fn fail_code(self, code: i32, msg: impl IntoDiagnostic) -> T {
+        match self {
+            Ok(t) => t,
+            Err(p) if p.is::<ErrataPanic>() => std::panic::resume_unwind(p),
+            Err(p) => msg
+                .into_diagnostic()
+                .with_error(payload_message(&*p))
+                .exit_code(code)
+                .fail(),
+        }
+    }
+}
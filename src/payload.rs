@@ -0,0 +1,176 @@
+//! The panic payload behind every errata failure.
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::panic::Location;
+use std::thread;
+
+use crate::{catch, config, Diagnostic, Note, Source, SpanLabel, Style};
+
+/// How serious a failure is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Severity {
+    Warning,
+    #[default]
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+
+    /// The style messages of this severity are printed with.
+    pub fn style(self) -> Style {
+        match self {
+            Severity::Warning => Style::WARNING,
+            Severity::Error => Style::ERROR,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The panic payload used by [`fail`](crate::FallibleExt::fail),
+/// [`error`](crate::error) and friends.
+///
+/// If you catch panics yourself, use [`downcast_payload`] to tell errata
+/// failures apart from ordinary panics.
+#[derive(Debug, Clone)]
+pub struct ErrataPanic {
+    diag: Diagnostic,
+    location: Option<&'static Location<'static>>,
+    thread: Option<String>,
+    /// Whether this has already been printed, e.g. by the thread it was
+    /// raised in.
+    pub(crate) reported: bool,
+}
+
+impl ErrataPanic {
+    pub(crate) fn without_location(mut self) -> Self {
+        self.location = None;
+        self
+    }
+
+    /// The message, without any styling.
+    pub fn message(&self) -> &str {
+        &self.diag.0.message
+    }
+
+    /// The `source` chain of the error, outermost first.
+    pub fn causes(&self) -> &[String] {
+        &self.diag.0.causes
+    }
+
+    /// The `note:` and `help:` lines, in the order they were added.
+    pub fn notes(&self) -> &[Note] {
+        &self.diag.0.notes
+    }
+
+    /// The named values attached with [`Diagnostic::field`].
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.diag.0.fields
+    }
+
+    /// The source text the [`labels`](ErrataPanic::labels) point into, if any.
+    pub fn source(&self) -> Option<&Source> {
+        self.diag.0.source.as_ref()
+    }
+
+    /// The labelled spans attached with [`Diagnostic::label`].
+    pub fn labels(&self) -> &[SpanLabel] {
+        &self.diag.0.labels
+    }
+
+    /// The stable error code set with [`Diagnostic::code`], if any.
+    pub fn error_code(&self) -> Option<&str> {
+        self.diag.0.code.as_deref()
+    }
+
+    /// The code the process exits with if this failure is caught by
+    /// `#[errata::catch]`.
+    pub fn code(&self) -> i32 {
+        self.diag.0.exit_code.unwrap_or_else(config::default_code)
+    }
+
+    /// Where the failure was raised.
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.location
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.diag.0.severity
+    }
+
+    /// The style the message is printed with, if color is enabled.
+    pub fn style(&self) -> Style {
+        self.diag.0.severity.style()
+    }
+
+    /// The name of the thread the failure was raised on, if it has one.
+    pub fn thread(&self) -> Option<&str> {
+        self.thread.as_deref()
+    }
+
+    /// The diagnostic this failure was built from.
+    pub fn diagnostic(&self) -> &Diagnostic {
+        &self.diag
+    }
+
+    /// Unwinds with this failure as the payload.
+    #[track_caller]
+    pub fn throw(self) -> ! {
+        // The default hook can't print failures, only `Box<dyn Any>`.
+        catch::install_hook();
+        std::panic::panic_any(self)
+    }
+}
+
+impl From<Diagnostic> for ErrataPanic {
+    /// Turns a diagnostic into a failure, located at the caller and on the
+    /// current thread.
+    #[track_caller]
+    fn from(diag: Diagnostic) -> Self {
+        Self {
+            diag,
+            location: Some(Location::caller()),
+            thread: thread::current().name().map(ToString::to_string),
+            reported: false,
+        }
+    }
+}
+
+impl Display for ErrataPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.diag.write_plain(f)
+    }
+}
+
+impl Error for ErrataPanic {}
+
+/// Gets the errata failure out of a panic payload, if it is one.
+///
+/// Usage:
+/// ```
+/// let payload = std::panic::catch_unwind(|| {
+///     errata::error!("Something went wrong");
+/// })
+/// .unwrap_err();
+///
+/// let failure = errata::downcast_payload(&payload).unwrap();
+/// assert_eq!(failure.message(), "Something went wrong");
+/// ```
+// Taking `&Box` rather than `&dyn Any` is deliberate: a `&Box<dyn Any>`
+// would silently coerce to a `&dyn Any` of the box itself.
+#[allow(clippy::borrowed_box)]
+pub fn downcast_payload(payload: &Box<dyn Any + Send>) -> Option<&ErrataPanic> {
+    (**payload).downcast_ref()
+}
@@ -0,0 +1,612 @@
+//! Rendering failures and panics for the user, or for other programs.
+
+use std::any::Any;
+use std::fmt::{self, Display, Write as _};
+use std::io::{self, Write};
+use std::panic;
+
+use crate::config::{self, OutputFormat};
+use crate::{
+    backtrace, catch, causes, explain, snippet, ErrataPanic, Note, Severity, Source, SpanLabel,
+    Style,
+};
+
+/// Whether a report came from errata or from an ordinary panic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// A failure from [`fail`](crate::FallibleExt::fail), [`error`](crate::error),
+    /// or a [`FatalError`](crate::FatalError).
+    Failure,
+    /// Any other panic, e.g. from `unwrap`.
+    Panic,
+}
+
+/// Where a panic happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicLocation {
+    file: String,
+    line: u32,
+    column: u32,
+}
+
+impl PanicLocation {
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+impl From<&panic::Location<'_>> for PanicLocation {
+    fn from(loc: &panic::Location<'_>) -> Self {
+        Self {
+            file: loc.file().to_string(),
+            line: loc.line(),
+            column: loc.column(),
+        }
+    }
+}
+
+impl Display for PanicLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Everything known about a failure at the point it's printed, for use by a
+/// [`Reporter`].
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub(crate) kind: PayloadKind,
+    pub(crate) message: String,
+    pub(crate) error_code: Option<String>,
+    pub(crate) fields: Vec<(String, String)>,
+    pub(crate) causes: Vec<String>,
+    pub(crate) notes: Vec<Note>,
+    pub(crate) source: Option<Source>,
+    pub(crate) labels: Vec<SpanLabel>,
+    pub(crate) code: i32,
+    pub(crate) severity: Severity,
+    pub(crate) style: Style,
+    pub(crate) location: Option<PanicLocation>,
+    pub(crate) thread: Option<String>,
+    pub(crate) backtrace: Option<String>,
+    pub(crate) color: bool,
+    pub(crate) verbose: bool,
+}
+
+impl Report {
+    /// A report for an errata failure, on the thread it was raised on.
+    pub(crate) fn failure(e: &ErrataPanic) -> Self {
+        Self {
+            kind: PayloadKind::Failure,
+            message: e.message().to_string(),
+            error_code: e.error_code().map(ToString::to_string),
+            fields: e.fields().to_vec(),
+            causes: e.causes().to_vec(),
+            notes: e.notes().to_vec(),
+            source: e.source().cloned(),
+            labels: e.labels().to_vec(),
+            code: e.code(),
+            severity: e.severity(),
+            style: e.style(),
+            location: e.location().map(PanicLocation::from),
+            thread: e.thread().map(ToString::to_string),
+            backtrace: None,
+            color: config::color().resolve(),
+            verbose: config::verbose(),
+        }
+    }
+
+    /// A report for an unexpected panic, on an unknown thread.
+    pub(crate) fn panic(message: &str, code: i32) -> Self {
+        Self {
+            kind: PayloadKind::Panic,
+            message: message.to_string(),
+            error_code: None,
+            fields: Vec::new(),
+            causes: Vec::new(),
+            notes: Vec::new(),
+            source: None,
+            labels: Vec::new(),
+            code,
+            severity: Severity::Error,
+            style: Style::PLAIN,
+            location: None,
+            thread: None,
+            backtrace: None,
+            color: config::color().resolve(),
+            verbose: config::verbose(),
+        }
+    }
+
+    /// Prints the report to stderr the way `#[errata::catch]` does, with the
+    /// configured reporter and output format.
+    pub fn print(&self) {
+        to_stderr(|err| self.write_to(err, config::output()));
+    }
+
+    /// Renders the report to a string, like [`render_to`](Report::render_to).
+    pub(crate) fn render(&self, options: &RenderOptions) -> String {
+        let mut out = Vec::new();
+        // Writing to a `Vec` can't fail, though a custom reporter could.
+        let _ = self.render_to(&mut out, options);
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// Writes the report with the given options instead of the configured
+    /// ones, apart from the reporter.
+    pub(crate) fn render_to(&self, out: &mut dyn Write, options: &RenderOptions) -> io::Result<()> {
+        let report = Report {
+            color: options.color,
+            verbose: options.verbose,
+            ..self.clone()
+        };
+        report.write_to(out, options.format)
+    }
+
+    /// Writes the report in the given format, using the configured reporter
+    /// for [`OutputFormat::Human`].
+    fn write_to(&self, out: &mut dyn Write, format: OutputFormat) -> io::Result<()> {
+        match format {
+            OutputFormat::Human => config::with_reporter(|r| r.report(out, self)),
+            OutputFormat::Json => JsonReporter.report(out, self),
+        }
+    }
+
+    pub fn kind(&self) -> PayloadKind {
+        self.kind
+    }
+
+    /// The message, without any styling.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The stable error code, such as `E042`, if any.
+    pub fn error_code(&self) -> Option<&str> {
+        self.error_code.as_deref()
+    }
+
+    /// Named values relevant to the failure, such as a path.
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// The `source` chain of the error, outermost first.
+    pub fn causes(&self) -> &[String] {
+        &self.causes
+    }
+
+    /// The `note:` and `help:` lines to print under the message.
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// The source text the [`labels`](Report::labels) point into, if any.
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Byte ranges of the [`source`](Report::source) to mark, with their text.
+    pub fn labels(&self) -> &[SpanLabel] {
+        &self.labels
+    }
+
+    /// The long-form explanation of the [`error_code`](Report::error_code),
+    /// if one was registered with [`explanations`](crate::explanations).
+    pub fn explanation(&self) -> Option<&'static str> {
+        explain::explain(self.error_code.as_deref()?)
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The code the process will exit with.
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+
+    /// The style the message should be printed with, if [`color`](Report::color)
+    /// is enabled.
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.as_ref()
+    }
+
+    /// The name of the thread that failed, if it has one.
+    pub fn thread(&self) -> Option<&str> {
+        self.thread.as_deref()
+    }
+
+    /// The trimmed backtrace of an unexpected panic, if backtraces are enabled.
+    pub fn backtrace(&self) -> Option<&str> {
+        self.backtrace.as_deref()
+    }
+
+    /// Whether the output supports color (see [`set_color`](crate::set_color)).
+    pub fn color(&self) -> bool {
+        self.color
+    }
+
+    /// Whether to include details meant for developers, such as where a
+    /// failure was raised (see [`set_verbose`](crate::set_verbose)).
+    pub fn verbose(&self) -> bool {
+        self.verbose
+    }
+}
+
+/// Prints a line to stderr, such as a summary that isn't part of a report.
+pub(crate) fn print_line(line: impl Display) {
+    to_stderr(|err| writeln!(err, "{line}"));
+}
+
+/// Runs `write` on a locked stderr.
+fn to_stderr(write: impl FnOnce(&mut io::StderrLock<'static>) -> io::Result<()>) {
+    // There's nowhere left to report a failure to write to stderr.
+    let _ = write(&mut io::stderr().lock());
+}
+
+/// Collects what `write` writes into a string.
+pub(crate) fn format_with(write: impl FnOnce(&mut String) -> fmt::Result) -> String {
+    let mut out = String::new();
+    // Writing to a `String` can't fail.
+    let _ = write(&mut out);
+    out
+}
+
+/// How [`render`] and [`render_to`] format a failure.
+///
+/// [`new`](RenderOptions::new) gives plain human-readable output, while
+/// [`from_config`](RenderOptions::from_config) matches what
+/// `#[errata::catch]` would print to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    color: bool,
+    verbose: bool,
+    format: OutputFormat,
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        Self {
+            color: false,
+            verbose: false,
+            format: OutputFormat::Human,
+        }
+    }
+
+    /// The options `#[errata::catch]` uses, from [`color`](crate::color),
+    /// [`verbose`](crate::verbose) and [`output`](crate::output).
+    pub fn from_config() -> Self {
+        Self {
+            color: config::color().resolve(),
+            verbose: config::verbose(),
+            format: config::output(),
+        }
+    }
+
+    /// Sets whether to include ANSI color codes.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets whether to include details meant for developers, such as where
+    /// a failure was raised.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Renders a caught panic payload the way `#[errata::catch]` would print it,
+/// without printing anything or exiting.
+///
+/// Works for errata failures and ordinary panics alike, though the location
+/// and backtrace of an ordinary panic aren't part of its payload, so only its
+/// message is rendered. Neither is the thread it happened on, so it's shown
+/// as `<unnamed>`. Use [`recover`](crate::recover) to get those too.
+///
+/// Usage:
+/// ```
+/// use errata::{FallibleExt, RenderOptions};
+///
+/// let res = std::panic::catch_unwind(|| "abc".parse::<i32>().fail("Invalid number"));
+///
+/// if let Err(payload) = res {
+///     let text = errata::render(&payload, &RenderOptions::new());
+///     assert_eq!(text, "Invalid number: invalid digit found in string\n");
+/// }
+/// ```
+#[allow(clippy::borrowed_box)]
+pub fn render(payload: &Box<dyn Any + Send>, options: &RenderOptions) -> String {
+    catch::report(&**payload).render(options)
+}
+
+/// Like [`render`], but writes to `out`.
+#[allow(clippy::borrowed_box)]
+pub fn render_to(
+    out: &mut dyn Write,
+    payload: &Box<dyn Any + Send>,
+    options: &RenderOptions,
+) -> io::Result<()> {
+    catch::report(&**payload).render_to(out, options)
+}
+
+/// Renders failures. See [`set_reporter`](crate::set_reporter).
+///
+/// Usage:
+/// ```no_run
+/// use std::io::{self, Write};
+/// use errata::{Report, Reporter};
+///
+/// struct Terse;
+///
+/// impl Reporter for Terse {
+///     fn report(&self, out: &mut dyn Write, report: &Report) -> io::Result<()> {
+///         writeln!(out, "error: {}", report.message())
+///     }
+/// }
+///
+/// errata::set_reporter(Terse);
+/// ```
+pub trait Reporter: Send + Sync {
+    /// Writes `report` to `out`, which is usually stderr.
+    fn report(&self, out: &mut dyn Write, report: &Report) -> io::Result<()>;
+}
+
+/// The default reporter, which prints errata failures as plain messages and
+/// unexpected panics the same way Rust does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HumanReporter;
+
+impl Reporter for HumanReporter {
+    fn report(&self, out: &mut dyn Write, report: &Report) -> io::Result<()> {
+        let thread = report.thread().unwrap_or("<unnamed>");
+        match (report.kind, report.location()) {
+            (PayloadKind::Panic, Some(loc)) => {
+                writeln!(out, "thread '{thread}' panicked at {loc}:")?
+            }
+            (PayloadKind::Panic, None) => writeln!(out, "thread '{thread}' panicked:")?,
+            (PayloadKind::Failure, _) if thread != "main" => {
+                writeln!(out, "thread '{thread}' failed:")?
+            }
+            (PayloadKind::Failure, _) => {}
+        }
+
+        let header = match &report.error_code {
+            Some(code) => format!("{}[{code}]: {}", report.severity, report.message),
+            None => report.message.clone(),
+        };
+        writeln!(out, "{}", report.style.paint(header, report.color))?;
+
+        if report.kind == PayloadKind::Failure && report.verbose {
+            if let Some(loc) = report.location() {
+                writeln!(out, "  --> {loc}")?;
+            }
+        }
+
+        if let Some(source) = &report.source {
+            let snippet = format_with(|f| {
+                snippet::write_snippet(f, source, &report.labels, report.style, report.color)
+            });
+            writeln!(out, "{}", &snippet[1..])?;
+        }
+
+        for (key, value) in &report.fields {
+            writeln!(out, "    {key}: {value}")?;
+        }
+
+        let causes = format_with(|f| causes::write_causes(f, &report.causes));
+        if let Some(causes) = causes.strip_prefix('\n') {
+            writeln!(out, "{causes}")?;
+        }
+
+        let bold = Style {
+            fg: None,
+            bold: true,
+        };
+        for note in &report.notes {
+            writeln!(
+                out,
+                "  = {}: {}",
+                bold.paint(note.label, report.color),
+                note.text
+            )?;
+        }
+
+        if let (Some(code), Some(_)) = (&report.error_code, report.explanation()) {
+            writeln!(out, "\n{}", explain::hint(code))?;
+        }
+
+        if report.kind == PayloadKind::Panic {
+            match report.backtrace() {
+                Some(bt) => {
+                    write!(out, "stack backtrace:\n{bt}")?;
+                    if !backtrace::is_full() {
+                        writeln!(out, "note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.")?;
+                    }
+                }
+                None => writeln!(
+                    out,
+                    "note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace"
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A reporter that writes a single-line JSON object per failure.
+/// See [`OutputFormat::Json`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn report(&self, out: &mut dyn Write, report: &Report) -> io::Result<()> {
+        let kind = match report.kind {
+            PayloadKind::Failure => "failure",
+            PayloadKind::Panic => "panic",
+        };
+        write!(out, "{{\"kind\":\"{kind}\"")?;
+        write!(out, ",\"message\":{}", Json(&report.message))?;
+
+        match &report.error_code {
+            Some(code) => write!(out, ",\"error_code\":{}", Json(code))?,
+            None => write!(out, ",\"error_code\":null")?,
+        }
+        write!(out, ",\"severity\":\"{}\"", report.severity)?;
+
+        write!(out, ",\"fields\":{{")?;
+        for (i, (key, value)) in report.fields.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{}:{}", Json(key), Json(value))?;
+        }
+        write!(out, "}}")?;
+
+        write!(out, ",\"causes\":[")?;
+        for (i, cause) in report.causes.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{}", Json(cause))?;
+        }
+        write!(out, "]")?;
+
+        write!(out, ",\"notes\":[")?;
+        for (i, note) in report.notes.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(
+                out,
+                "{{\"label\":\"{}\",\"text\":{}}}",
+                note.label,
+                Json(&note.text)
+            )?;
+        }
+        write!(out, "]")?;
+
+        match &report.source {
+            Some(source) => {
+                write!(
+                    out,
+                    ",\"snippet\":{{\"name\":{},\"labels\":[",
+                    Json(source.name())
+                )?;
+                for (i, label) in report.labels.iter().enumerate() {
+                    if i > 0 {
+                        write!(out, ",")?;
+                    }
+                    let (line, column) = source.line_col(label.span.start);
+                    write!(
+                        out,
+                        "{{\"start\":{},\"end\":{},\"line\":{line},\"column\":{column},\"text\":{}}}",
+                        label.span.start,
+                        label.span.end,
+                        Json(&label.text)
+                    )?;
+                }
+                write!(out, "]}}")?;
+            }
+            None => write!(out, ",\"snippet\":null")?,
+        }
+
+        write!(out, ",\"code\":{}", report.code)?;
+
+        match &report.location {
+            Some(loc) => write!(
+                out,
+                ",\"location\":{{\"file\":{},\"line\":{},\"column\":{}}}",
+                Json(&loc.file),
+                loc.line,
+                loc.column
+            )?,
+            None => write!(out, ",\"location\":null")?,
+        }
+
+        match &report.thread {
+            Some(thread) => write!(out, ",\"thread\":{}", Json(thread))?,
+            None => write!(out, ",\"thread\":null")?,
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+/// Displays a string as a quoted, escaped JSON string.
+struct Json<'a>(&'a str);
+
+impl Display for Json<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{panic, thread};
+
+    use super::{render, Json, RenderOptions};
+    use crate::FallibleExt;
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(Json("plain").to_string(), r#""plain""#);
+        assert_eq!(Json("say \"hi\"\\now").to_string(), r#""say \"hi\"\\now""#);
+        assert_eq!(Json("a\nb\r\tc").to_string(), r#""a\nb\r\tc""#);
+        assert_eq!(Json("\u{1b}[1m").to_string(), r#""\u001b[1m""#);
+        assert_eq!(Json("ünïcødé ✓").to_string(), "\"ünïcødé ✓\"");
+    }
+
+    #[test]
+    fn renders_the_thread_that_failed() {
+        let payload = thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(|| panic::catch_unwind(|| None::<i32>.fail("Missing value")).unwrap_err())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(
+            render(&payload, &RenderOptions::new()),
+            "thread 'worker' failed:\nMissing value\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Source snippets with labelled spans, rendered like rustc's.
+
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+use crate::Style;
+
+/// The style of line numbers and the gutter, bright blue like rustc's.
+const GUTTER: Style = Style {
+    fg: Some(12),
+    bold: true,
+};
+
+/// A named source text, such as a config file, that failures can point into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    name: String,
+    text: String,
+}
+
+impl Source {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            text: text.into(),
+        }
+    }
+
+    /// The name of the source, usually a file path.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The 1-based line and column of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = floor_char_boundary(&self.text, offset);
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+/// A byte range in a [`Source`], with text to print next to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanLabel {
+    pub span: Range<usize>,
+    pub text: String,
+}
+
+/// Writes a snippet of `source` with carets under each label, each line
+/// preceded by a newline. `style` is used for the carets and labels.
+pub(crate) fn write_snippet(
+    f: &mut impl Write,
+    source: &Source,
+    labels: &[SpanLabel],
+    style: Style,
+    color: bool,
+) -> fmt::Result {
+    let text = &source.text;
+
+    // (line number, line start, start, end, label) for each label, clamped to
+    // the first line it touches.
+    let mut spans: Vec<_> = labels
+        .iter()
+        .map(|label| {
+            let start = floor_char_boundary(text, label.span.start);
+            let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+            let end = floor_char_boundary(text, label.span.end).clamp(start, line_end);
+            let line = text[..start].matches('\n').count() + 1;
+            (line, line_start, start, end, label.text.as_str())
+        })
+        .collect();
+    spans.sort_by_key(|&(line, _, start, ..)| (line, start));
+
+    let width = spans.last().map_or(1, |&(line, ..)| line.to_string().len());
+    let pad = " ".repeat(width);
+    let gutter = |s: &str| GUTTER.paint(s.to_string(), color);
+
+    match labels.first() {
+        Some(label) => {
+            let (line, col) = source.line_col(label.span.start);
+            write!(f, "\n{pad}{} {}:{line}:{col}", gutter("-->"), source.name)?;
+        }
+        None => write!(f, "\n{pad}{} {}", gutter("-->"), source.name)?,
+    }
+    write!(f, "\n{pad} {}", gutter("|"))?;
+
+    let mut last_line = None;
+    for &(line, line_start, start, end, label) in &spans {
+        if last_line != Some(line) {
+            let line_end = text[line_start..]
+                .find('\n')
+                .map_or(text.len(), |i| line_start + i);
+            let number = format!("{line:>width$}");
+            write!(
+                f,
+                "\n{} {} {}",
+                gutter(&number),
+                gutter("|"),
+                text[line_start..line_end].replace('\t', "    ")
+            )?;
+            last_line = Some(line);
+        }
+
+        let indent = display_width(&text[line_start..start]);
+        let carets = "^".repeat(display_width(&text[start..end]).max(1));
+        let marker = if label.is_empty() {
+            carets
+        } else {
+            format!("{carets} {label}")
+        };
+        write!(
+            f,
+            "\n{pad} {} {}{}",
+            gutter("|"),
+            " ".repeat(indent),
+            style.paint(marker, color)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The width of `s` when printed, with tabs expanded to four spaces.
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
+
+/// Clamps `offset` to the text and moves it back to a character boundary.
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_snippet, Source, SpanLabel};
+    use crate::Style;
+
+    fn snippet(text: &str, labels: &[(std::ops::Range<usize>, &str)]) -> String {
+        let source = Source::new("test.txt", text);
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(span, text)| SpanLabel {
+                span: span.clone(),
+                text: text.to_string(),
+            })
+            .collect();
+        let mut out = String::new();
+        write_snippet(&mut out, &source, &labels, Style::PLAIN, false).unwrap();
+        out
+    }
+
+    #[test]
+    fn counts_columns_in_chars() {
+        let source = Source::new("test.txt", "héllo\nwörld");
+        assert_eq!(source.line_col(0), (1, 1));
+        assert_eq!(source.line_col(3), (1, 3));
+        assert_eq!(source.line_col(7), (2, 1));
+        assert_eq!(source.line_col(10), (2, 3));
+        // Inside the `é`, which starts at byte 1.
+        assert_eq!(source.line_col(2), (1, 2));
+    }
+
+    #[test]
+    fn underlines_multi_byte_text() {
+        assert_eq!(
+            snippet("héllo wörld", &[(7..13, "here")]),
+            "\n --> test.txt:1:7\
+             \n  |\
+             \n1 | héllo wörld\
+             \n  |       ^^^^^ here"
+        );
+    }
+
+    #[test]
+    fn expands_tabs() {
+        assert_eq!(
+            snippet("\tlet x = 1;", &[(5..6, "here")]),
+            "\n --> test.txt:1:6\
+             \n  |\
+             \n1 |     let x = 1;\
+             \n  |         ^ here"
+        );
+    }
+
+    #[test]
+    fn clamps_spans_past_the_end() {
+        assert_eq!(
+            snippet("abc", &[(10..12, "missing")]),
+            "\n --> test.txt:1:4\
+             \n  |\
+             \n1 | abc\
+             \n  |    ^ missing"
+        );
+    }
+
+    #[test]
+    fn clamps_spans_to_their_first_line() {
+        assert_eq!(
+            snippet("abc\ndef", &[(1..6, "here")]),
+            "\n --> test.txt:1:2\
+             \n  |\
+             \n1 | abc\
+             \n  |  ^^ here"
+        );
+    }
+
+    #[test]
+    fn prints_a_line_once_for_several_labels() {
+        assert_eq!(
+            snippet("let x = y;", &[(8..9, "second"), (4..5, "first")]),
+            "\n --> test.txt:1:9\
+             \n  |\
+             \n1 | let x = y;\
+             \n  |     ^ first\
+             \n  |         ^ second"
+        );
+    }
+
+    #[test]
+    fn pads_line_numbers_to_the_widest() {
+        let text = "a\n".repeat(9) + "b\n";
+        assert_eq!(
+            snippet(&text, &[(0..1, "one"), (18..19, "ten")]),
+            "\n  --> test.txt:1:1\
+             \n   |\
+             \n 1 | a\
+             \n   | ^ one\
+             \n10 | b\
+             \n   | ^ ten"
+        );
+    }
+
+    #[test]
+    fn leaves_out_empty_labels() {
+        assert_eq!(
+            snippet("abc", &[(1..2, "")]),
+            "\n --> test.txt:1:2\
+             \n  |\
+             \n1 | abc\
+             \n  |  ^"
+        );
+    }
+}
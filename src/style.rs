@@ -0,0 +1,67 @@
+//! Terminal styling, applied only when a failure is printed.
+
+use std::fmt::{self, Display};
+
+/// How a message should look when printed to a terminal.
+///
+/// Styles are stored alongside the plain message rather than baked into it,
+/// so anything reading the message later doesn't see escape codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    /// The foreground color, as an index into the 256-color ANSI palette.
+    pub fg: Option<u8>,
+    pub bold: bool,
+}
+
+impl Style {
+    /// No styling at all.
+    pub const PLAIN: Style = Style {
+        fg: None,
+        bold: false,
+    };
+
+    /// Bold red, used for failure messages.
+    pub const ERROR: Style = Style {
+        fg: Some(1),
+        bold: true,
+    };
+
+    /// Bold yellow, used for warnings.
+    pub const WARNING: Style = Style {
+        fg: Some(3),
+        bold: true,
+    };
+
+    /// Wraps `text` so that it displays with this style if `enabled`.
+    pub fn paint<D: Display>(self, text: D, enabled: bool) -> Painted<D> {
+        Painted {
+            style: self,
+            text,
+            enabled,
+        }
+    }
+}
+
+/// Text that displays with a [`Style`]. See [`Style::paint`].
+#[derive(Debug, Clone, Copy)]
+pub struct Painted<D> {
+    style: Style,
+    text: D,
+    enabled: bool,
+}
+
+impl<D: Display> Display for Painted<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.enabled || self.style == Style::PLAIN {
+            return write!(f, "{}", self.text);
+        }
+
+        if let Some(fg) = self.style.fg {
+            write!(f, "\x1b[38;5;{fg}m")?;
+        }
+        if self.style.bold {
+            f.write_str("\x1b[1m")?;
+        }
+        write!(f, "{}\x1b[0m", self.text)
+    }
+}
@@ -0,0 +1,110 @@
+//! Helpers for testing code that fails with errata.
+
+use crate::config::{self, ColorChoice};
+use crate::{catch, payload_message, ErrataPanic, IntoDiagnostic, RenderOptions};
+
+/// Asserts that `f` fails with the given message, exit code and notes, and
+/// returns the failure for any further checks.
+///
+/// `expected` can be a plain message, which must have no notes and the
+/// default exit code, or a [`Diagnostic`](crate::Diagnostic) with the notes
+/// and exit code expected.
+///
+/// Usage:
+/// ```no_run
+/// use errata::testing::assert_fails;
+/// use errata::{Diagnostic, FallibleExt};
+///
+/// fn parse_port(s: &str) -> u16 {
+///     s.parse().fail_code(2, "Invalid port")
+/// }
+///
+/// assert_fails(
+///     || parse_port("http"),
+///     Diagnostic::new("Invalid port: invalid digit found in string").exit_code(2),
+/// );
+/// ```
+#[track_caller]
+pub fn assert_fails<T>(f: impl FnOnce() -> T, expected: impl IntoDiagnostic) -> ErrataPanic {
+    let expected = expected.into_diagnostic();
+
+    let payload = match catch::run(f) {
+        Ok(_) => panic!(
+            "expected a failure with message {:?}, but there was none",
+            expected.0.message
+        ),
+        Err(caught) => caught.payload,
+    };
+    let e = match payload.downcast::<ErrataPanic>() {
+        Ok(e) => *e,
+        Err(payload) => panic!(
+            "expected a failure with message {:?}, but got a panic: {}",
+            expected.0.message,
+            payload_message(&*payload)
+        ),
+    };
+
+    assert_eq!(e.message(), expected.0.message, "failure message differs");
+    assert_eq!(
+        e.code(),
+        expected.0.exit_code.unwrap_or_else(config::default_code),
+        "failure exit code differs"
+    );
+    assert_eq!(e.notes(), expected.0.notes, "failure notes differ");
+    e
+}
+
+/// Runs `f` and returns what `#[errata::catch]` would print to stderr if it
+/// failed, or an empty string if it didn't.
+///
+/// Color is only used if it's set to
+/// [`ColorChoice::Always`], and the report is rendered as if `f` ran on the
+/// main thread, so the output doesn't depend on where or how the tests are
+/// run. Test harnesses usually run each test on a thread named after it,
+/// which would otherwise add a `thread '...' failed:` header.
+///
+/// Usage:
+/// ```
+/// use errata::FallibleExt;
+///
+/// let output = errata::testing::capture_report(|| {
+///     None::<i32>.fail("Missing value");
+/// });
+///
+/// assert_eq!(output, "Missing value\n");
+/// ```
+pub fn capture_report<T>(f: impl FnOnce() -> T) -> String {
+    match catch::run(f) {
+        Ok(_) => String::new(),
+        Err(caught) => {
+            let color = config::color() == ColorChoice::Always;
+            let mut report = caught.report();
+            report.thread = Some("main".to_string());
+            report.render(&RenderOptions::from_config().color(color))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::capture_report;
+    use crate::FallibleExt;
+
+    #[test]
+    fn captures_nothing_without_a_failure() {
+        assert_eq!(capture_report(|| 1), "");
+    }
+
+    #[test]
+    fn leaves_out_the_thread_name() {
+        let output = thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(|| capture_report(|| None::<i32>.fail("Missing value")))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(output, "Missing value\n");
+    }
+}
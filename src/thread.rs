@@ -0,0 +1,191 @@
+//! Threads that print their failures the same way `#[errata::catch]` does.
+//!
+//! Without these, a failure in a spawned thread is silent until the thread is
+//! joined, and is then just an opaque payload.
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle, ScopedJoinHandle};
+
+use crate::{catch, crash, downcast_payload, ErrataPanic};
+
+/// Spawns a thread that prints its failures as soon as they happen.
+///
+/// The failure is still returned from [`join`](JoinHandle::join), with the
+/// [`ErrataPanic`] payload intact.
+///
+/// Usage:
+/// ```no_run
+/// use errata::FallibleExt;
+///
+/// let handle = errata::thread::spawn(|| {
+///     "abc".parse::<i32>().fail("Invalid number")
+/// });
+///
+/// // Prints "thread '<unnamed>' failed:" and the message before this returns.
+/// assert!(handle.join().is_err());
+/// ```
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new().spawn(f).expect("failed to spawn thread")
+}
+
+/// Like [`std::thread::scope`], but threads spawned in the scope print their
+/// failures as soon as they happen.
+///
+/// If a thread that wasn't joined manually fails, the scope re-raises its
+/// [`ErrataPanic`] once all threads are done, instead of a generic panic.
+///
+/// Usage:
+/// ```no_run
+/// use errata::FallibleExt;
+///
+/// errata::thread::scope(|s| {
+///     for input in ["1", "2", "three"] {
+///         s.spawn(move || input.parse::<i32>().fail("Invalid number"));
+///     }
+/// });
+/// ```
+pub fn scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(Scope<'scope, 'env>) -> T,
+{
+    let failure = Arc::new(Mutex::new(None));
+
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        thread::scope(|inner| {
+            f(Scope {
+                inner,
+                fatal: false,
+                failure: failure.clone(),
+            })
+        })
+    }));
+
+    match res {
+        Ok(t) => t,
+        Err(payload) if payload.is::<ErrataPanic>() => panic::resume_unwind(payload),
+        Err(payload) => match failure.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            Some(e) => panic::resume_unwind(Box::new(e)),
+            None => panic::resume_unwind(payload),
+        },
+    }
+}
+
+/// A scope to spawn threads in. See [`scope`].
+#[derive(Clone)]
+pub struct Scope<'scope, 'env: 'scope> {
+    inner: &'scope thread::Scope<'scope, 'env>,
+    fatal: bool,
+    /// The first failure from a thread in the scope.
+    failure: Arc<Mutex<Option<ErrataPanic>>>,
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Makes failures in threads spawned from this handle exit the whole
+    /// process, the way a failure in `main` does.
+    pub fn fatal(self, fatal: bool) -> Self {
+        Self { fatal, ..self }
+    }
+
+    /// Spawns a thread in the scope. See [`std::thread::Scope::spawn`].
+    pub fn spawn<F, T>(&self, f: F) -> ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let fatal = self.fatal;
+        let failure = self.failure.clone();
+        self.inner.spawn(move || guard(f, fatal, Some(&failure)))
+    }
+}
+
+/// Configures a thread before spawning it, like [`std::thread::Builder`].
+#[derive(Debug)]
+pub struct Builder {
+    inner: thread::Builder,
+    fatal: bool,
+}
+
+impl Builder {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            inner: thread::Builder::new(),
+            fatal: false,
+        }
+    }
+
+    /// Names the thread. The name is printed alongside its failures.
+    pub fn name(self, name: String) -> Self {
+        Self {
+            inner: self.inner.name(name),
+            ..self
+        }
+    }
+
+    pub fn stack_size(self, size: usize) -> Self {
+        Self {
+            inner: self.inner.stack_size(size),
+            ..self
+        }
+    }
+
+    /// Makes a failure in the thread exit the whole process, the way a
+    /// failure in `main` does. Destructors on other threads are not run.
+    pub fn fatal(self, fatal: bool) -> Self {
+        Self { fatal, ..self }
+    }
+
+    /// Spawns the thread. See [`std::thread::Builder::spawn`].
+    pub fn spawn<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let fatal = self.fatal;
+        self.inner.spawn(move || guard(f, fatal, None))
+    }
+}
+
+/// Runs the body of a thread, printing any failure that wasn't already
+/// printed before passing it on.
+///
+/// The first errata failure is also stored in `failure`, if given.
+fn guard<T>(f: impl FnOnce() -> T, fatal: bool, failure: Option<&Mutex<Option<ErrataPanic>>>) -> T {
+    let caught = match catch::run(f) {
+        Ok(t) => return t,
+        Err(caught) => caught,
+    };
+
+    let report = caught.report();
+    let mut payload = caught.payload;
+    if downcast_payload(&payload).is_some_and(|e| e.reported) {
+        // Already printed, e.g. by a `Collector`.
+    } else if fatal {
+        crash::print_fatal(&report);
+    } else {
+        report.print();
+    }
+    if fatal {
+        process::exit(report.code);
+    }
+
+    if let Some(e) = payload.downcast_mut::<ErrataPanic>() {
+        e.reported = true;
+
+        if let Some(failure) = failure {
+            failure
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_or_insert_with(|| e.clone());
+        }
+    }
+
+    panic::resume_unwind(payload)
+}